@@ -0,0 +1,70 @@
+use super::{environment::EnvironmentMap, light::Light, sky::PreethamSky, Color, Direction, Vec3};
+
+/*
+    What rays escaping the scene see. Backgrounds bright enough to light the scene
+    also expose themselves as a light, so the integrator samples them with shadow rays.
+*/
+
+pub trait Background: Send + Sync {
+    fn value(&self, direction: &Direction) -> Color;
+    fn as_light(&self) -> Option<&dyn Light> {
+        None
+    }
+}
+
+pub struct Constant {
+    color: Color,
+}
+
+impl Constant {
+    pub fn new(color: Color) -> Self {
+        Constant { color }
+    }
+}
+
+impl Background for Constant {
+    fn value(&self, _direction: &Direction) -> Color {
+        self.color
+    }
+}
+
+// blends from bottom to top with the height of the direction
+pub struct VerticalGradient {
+    bottom: Color,
+    top: Color,
+}
+
+impl VerticalGradient {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        VerticalGradient { bottom, top }
+    }
+    // the white to blue sky of the first book
+    pub fn sky() -> Self {
+        VerticalGradient::new(Vec3::new(1., 1., 1.), Vec3::new(0.5, 0.7, 1.))
+    }
+}
+
+impl Background for VerticalGradient {
+    fn value(&self, direction: &Direction) -> Color {
+        let t = 0.5 * (direction.normalize().y() + 1.);
+        (1. - t) * self.bottom + t * self.top
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, direction: &Direction) -> Color {
+        self.radiance(direction)
+    }
+    fn as_light(&self) -> Option<&dyn Light> {
+        Some(self)
+    }
+}
+
+impl Background for PreethamSky {
+    fn value(&self, direction: &Direction) -> Color {
+        self.radiance(direction)
+    }
+    fn as_light(&self) -> Option<&dyn Light> {
+        Some(self)
+    }
+}
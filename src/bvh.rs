@@ -3,6 +3,7 @@ use super::Arc;
 use super::HitRecord;
 use super::Ray;
 use super::AABB;
+use super::{Direction, Position};
 use super::{Hittable, HittableList};
 use std::{cmp::Ordering, f64};
 
@@ -10,14 +11,34 @@ pub struct BVHNode {
     root_box: AABB,
     left: Arc<dyn Hittable>,
     right: Arc<dyn Hittable>,
+    // areas of the children, computed once since light sampling asks for them at every step
+    left_area: f64,
+    right_area: f64,
 }
 
 impl BVHNode {
     fn new(root_box: AABB, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        // a single object ends up as both children, so it only counts once
+        let (left_area, right_area) = if Arc::ptr_eq(&left, &right) {
+            (left.area(), 0.)
+        } else {
+            (left.area(), right.area())
+        };
         BVHNode {
             root_box,
             left,
             right,
+            left_area,
+            right_area,
+        }
+    }
+
+    fn pick_by_area(&self) -> &Arc<dyn Hittable> {
+        let (left, right) = (self.left_area, self.right_area);
+        if utils::random_double() * (left + right) < left {
+            &self.left
+        } else {
+            &self.right
         }
     }
 
@@ -114,4 +135,32 @@ impl Hittable for BVHNode {
     fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
         Some(self.root_box)
     }
+
+    // sampling descends into a child proportionally to its area
+    fn area(&self) -> f64 {
+        self.left_area + self.right_area
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.pick_by_area().sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (direction, _) = self.pick_by_area().sample_direction(origin)?;
+        let pdf = self.direction_pdf(origin, &direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some((direction, pdf))
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        if Arc::ptr_eq(&self.left, &self.right) {
+            return self.left.direction_pdf(origin, direction);
+        }
+        let (left, right) = (self.left_area, self.right_area);
+        if left + right <= 0. {
+            return 0.;
+        }
+        (left * self.left.direction_pdf(origin, direction)
+            + right * self.right.direction_pdf(origin, direction))
+            / (left + right)
+    }
 }
@@ -1,18 +1,99 @@
+use super::ray::RayDifferentials;
 use super::utils;
 use super::Ray;
-use super::Vec3;
+use super::{Direction, Position, Vec3};
+use std::f64::consts::PI;
+
+/*
+    What raytrace needs from a camera: the ray through a point of the image, s and t in [0, 1]
+    from the lower left corner, along with the differentials towards the pixels ds and dt away.
+    Projections that don't cover the whole image return None outside of it, which renders black.
+*/
+pub trait CameraModel: Send + Sync {
+    fn aspect_ratio(&self) -> f64;
+    fn generate_ray(&self, s: f64, t: f64, ds: f64, dt: f64) -> Option<Ray>;
+}
+
+// where a camera sits, which way it looks (down -w, with v up) and when its shutter is open
+#[derive(Copy, Clone)]
+pub struct View {
+    pub origin: Position,
+    pub u: Direction,
+    pub v: Direction,
+    pub w: Direction,
+    time0: Option<f64>,
+    time1: Option<f64>,
+}
+
+impl View {
+    pub fn new(
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        time0: Option<f64>,
+        time1: Option<f64>,
+    ) -> Self {
+        let w = (lookfrom - lookat).normalize();
+        let u = vup.cross(w).normalize();
+        View {
+            origin: lookfrom,
+            u,
+            v: w.cross(u),
+            w,
+            time0,
+            time1,
+        }
+    }
+
+    fn shutter_time(&self) -> f64 {
+        utils::random_from_range(self.time0.unwrap_or(0.), self.time1.unwrap_or(1.))
+    }
+
+    // a, b and c are the view's right, up and backward components
+    fn world_direction(&self, a: f64, b: f64, c: f64) -> Direction {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    // rays from a single point, with differentials from the directions at the neighbouring pixels
+    fn pinhole_ray<F: Fn(f64, f64) -> Option<Direction>>(
+        &self,
+        s: f64,
+        t: f64,
+        ds: f64,
+        dt: f64,
+        direction: F,
+    ) -> Option<Ray> {
+        let d = direction(s, t)?;
+        let dx = direction(s + ds, t).unwrap_or(d);
+        let dy = direction(s, t + dt).unwrap_or(d);
+        Some(
+            Ray::new(self.origin, d, Some(self.shutter_time())).with_differentials(Some(
+                RayDifferentials {
+                    rx_origin: self.origin,
+                    rx_direction: dx,
+                    ry_origin: self.origin,
+                    ry_direction: dy,
+                },
+            )),
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // parallel rays along the view direction, starting on the plane through lookfrom
+    Orthographic,
+}
 
 pub struct Camera {
     pub aspect_ratio: f64,
+    projection: Projection,
+    view: View,
     horizontal: Vec3,
     vertical: Vec3,
-    origin: Vec3,
     lower_left: Vec3,
-    u: Vec3,
-    v: Vec3,
     lens_radius: f64,
-    time0: Option<f64>,
-    time1: Option<f64>,
 }
 
 impl Camera {
@@ -32,37 +113,298 @@ impl Camera {
         let viewport_height: f64 = 2. * h;
         let viewport_width: f64 = aspect_ratio * viewport_height;
 
-        let w = (lookfrom - lookat).normalize();
-        let u = vup.cross(w).normalize();
-        let v = w.cross(u);
-
-        let origin = lookfrom;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left = origin - horizontal / 2. - vertical / 2. - focus_dist * w;
+        let view = View::new(lookfrom, lookat, vup, time0, time1);
+        let horizontal = focus_dist * viewport_width * view.u;
+        let vertical = focus_dist * viewport_height * view.v;
         Camera {
             aspect_ratio,
+            projection: Projection::Perspective,
+            view,
             horizontal,
             vertical,
-            origin,
-            lower_left,
-            u,
-            v,
+            lower_left: view.origin - horizontal / 2. - vertical / 2. - focus_dist * view.w,
             lens_radius: aperture / 2.,
-            time0,
-            time1,
         }
     }
+    // view_width is the width of the region seen, in scene units. everything is in focus
+    pub fn orthographic(
+        aspect_ratio: f64,
+        view_width: f64,
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        time0: Option<f64>,
+        time1: Option<f64>,
+    ) -> Self {
+        let view = View::new(lookfrom, lookat, vup, time0, time1);
+        let horizontal = view_width * view.u;
+        let vertical = view_width / aspect_ratio * view.v;
+        Camera {
+            aspect_ratio,
+            projection: Projection::Orthographic,
+            view,
+            horizontal,
+            vertical,
+            lower_left: view.origin - horizontal / 2. - vertical / 2.,
+            lens_radius: 0.,
+        }
+    }
+    // the position and orientation of the camera, to look at the same scene through another projection
+    pub fn view(&self) -> View {
+        self.view
+    }
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+        if self.projection == Projection::Orthographic {
+            return Ray::new(
+                self.lower_left + s * self.horizontal + t * self.vertical,
+                -self.view.w,
+                Some(self.view.shutter_time()),
+            );
+        }
         let rd = self.lens_radius * Vec3::random_in_unit_disk();
-        let offset = self.u * rd.x() + self.v * rd.y();
+        let origin = self.view.origin + self.view.world_direction(rd.x(), rd.y(), 0.);
         Ray::new(
-            self.origin + offset,
-            self.lower_left + s * self.horizontal + t * self.vertical - self.origin - offset,
-            Some(utils::random_from_range(
-                self.time0.unwrap_or(0.),
-                self.time1.unwrap_or(1.),
-            )),
+            origin,
+            self.lower_left + s * self.horizontal + t * self.vertical - origin,
+            Some(self.view.shutter_time()),
         )
     }
+    // same as get_ray, along with the rays through the neighbouring pixels, ds and dt apart
+    pub fn get_ray_differential(&self, s: f64, t: f64, ds: f64, dt: f64) -> Ray {
+        let r = self.get_ray(s, t);
+        // neighbouring orthographic rays are shifted rather than turned
+        let differentials = match self.projection {
+            Projection::Perspective => RayDifferentials {
+                rx_origin: r.origin(),
+                rx_direction: r.direction() + ds * self.horizontal,
+                ry_origin: r.origin(),
+                ry_direction: r.direction() + dt * self.vertical,
+            },
+            Projection::Orthographic => RayDifferentials {
+                rx_origin: r.origin() + ds * self.horizontal,
+                rx_direction: r.direction(),
+                ry_origin: r.origin() + dt * self.vertical,
+                ry_direction: r.direction(),
+            },
+        };
+        r.with_differentials(Some(differentials))
+    }
+}
+
+impl CameraModel for Camera {
+    fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+    fn generate_ray(&self, s: f64, t: f64, ds: f64, dt: f64) -> Option<Ray> {
+        Some(self.get_ray_differential(s, t, ds, dt))
+    }
+}
+
+// the whole sphere of directions, longitude across the image and latitude up it, in a 2:1 image
+pub struct Equirectangular {
+    view: View,
+}
+
+impl Equirectangular {
+    pub fn new(view: View) -> Self {
+        Equirectangular { view }
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn aspect_ratio(&self) -> f64 {
+        2.
+    }
+    // the center of the image looks down the view direction
+    fn generate_ray(&self, s: f64, t: f64, ds: f64, dt: f64) -> Option<Ray> {
+        self.view.pinhole_ray(s, t, ds, dt, |s, t| {
+            let phi = 2. * PI * (s - 0.5);
+            let latitude = PI * (t - 0.5);
+            Some(self.view.world_direction(
+                latitude.cos() * phi.sin(),
+                latitude.sin(),
+                -latitude.cos() * phi.cos(),
+            ))
+        })
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum FisheyeMapping {
+    // distance from the center proportional to the angle off the view direction
+    Equidistant,
+    // equal areas of the image cover equal solid angles
+    Equisolid,
+}
+
+// a circular image in a square, fov in degrees across the circle, up to 360
+pub struct Fisheye {
+    view: View,
+    half_fov: f64,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    pub fn new(view: View, fov: f64, mapping: FisheyeMapping) -> Self {
+        Fisheye {
+            view,
+            half_fov: utils::degrees_to_radians(fov.min(360.)) / 2.,
+            mapping,
+        }
+    }
+
+    fn direction(&self, s: f64, t: f64) -> Option<Direction> {
+        let (x, y) = (2. * s - 1., 2. * t - 1.);
+        let r = (x * x + y * y).sqrt();
+        if r > 1. {
+            return None;
+        }
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => {
+                2. * utils::clamp(r * (self.half_fov / 2.).sin(), -1., 1.).asin()
+            }
+        };
+        let phi = y.atan2(x);
+        Some(self.view.world_direction(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        ))
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn aspect_ratio(&self) -> f64 {
+        1.
+    }
+    fn generate_ray(&self, s: f64, t: f64, ds: f64, dt: f64) -> Option<Ray> {
+        self.view
+            .pinhole_ray(s, t, ds, dt, |s, t| self.direction(s, t))
+    }
+}
+
+/*
+    the six 90 degree views around the camera side by side in a 6:1 strip, in the order
+    right, left, up, down, front, back. up and down have the back of the view at the top
+*/
+pub struct CubeMap {
+    view: View,
+}
+
+impl CubeMap {
+    pub fn new(view: View) -> Self {
+        CubeMap { view }
+    }
+
+    // forward, right and up of each face, as components along the view's u, v, w
+    const FACES: [[(f64, f64, f64); 3]; 6] = [
+        [(1., 0., 0.), (0., 0., 1.), (0., 1., 0.)],
+        [(-1., 0., 0.), (0., 0., -1.), (0., 1., 0.)],
+        [(0., 1., 0.), (1., 0., 0.), (0., 0., 1.)],
+        [(0., -1., 0.), (1., 0., 0.), (0., 0., -1.)],
+        [(0., 0., -1.), (1., 0., 0.), (0., 1., 0.)],
+        [(0., 0., 1.), (-1., 0., 0.), (0., 1., 0.)],
+    ];
+
+    fn direction(&self, face: usize, a: f64, b: f64) -> Direction {
+        let component = |(x, y, z): (f64, f64, f64)| self.view.world_direction(x, y, z);
+        let [forward, right, up] = CubeMap::FACES[face];
+        component(forward) + (2. * a - 1.) * component(right) + (2. * b - 1.) * component(up)
+    }
+}
+
+impl CameraModel for CubeMap {
+    fn aspect_ratio(&self) -> f64 {
+        6.
+    }
+    // neighbouring directions stay on the same face so the seams don't blur textures
+    fn generate_ray(&self, s: f64, t: f64, ds: f64, dt: f64) -> Option<Ray> {
+        let face = ((s * 6.) as usize).min(5);
+        let a = s * 6. - face as f64;
+        self.view
+            .pinhole_ray(a, t, 6. * ds, dt, |a, b| Some(self.direction(face, a, b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let cam = Camera::orthographic(
+            2.,
+            4.,
+            Vec3::new(0., 0., 5.),
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            None,
+            None,
+        );
+        let (a, b) = (cam.get_ray(0., 0.), cam.get_ray(1., 1.));
+        assert_eq!(a.direction().z(), -1.);
+        assert!((a.direction() - b.direction()).length() < 1e-12);
+        // the view is 4 units wide and 2 high, centered on lookfrom
+        assert!((a.origin() - Vec3::new(-2., -1., 5.)).length() < 1e-12);
+        assert!((b.origin() - Vec3::new(2., 1., 5.)).length() < 1e-12);
+    }
+
+    #[test]
+    fn panoramic_projections() {
+        let view = View::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            None,
+            None,
+        );
+        let direction = |cam: &dyn CameraModel, s: f64, t: f64| {
+            cam.generate_ray(s, t, 0.01, 0.01)
+                .map(|r| r.direction().normalize())
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-9;
+
+        let panorama = Equirectangular::new(view);
+        assert!(close(
+            direction(&panorama, 0.5, 0.5).unwrap(),
+            Vec3::new(0., 0., -1.)
+        ));
+        assert!(close(
+            direction(&panorama, 0.75, 0.5).unwrap(),
+            Vec3::new(1., 0., 0.)
+        ));
+        assert!(close(
+            direction(&panorama, 0.5, 1.).unwrap(),
+            Vec3::new(0., 1., 0.)
+        ));
+
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Fisheye::new(view, 180., mapping);
+            assert!(close(
+                direction(&fisheye, 0.5, 0.5).unwrap(),
+                Vec3::new(0., 0., -1.)
+            ));
+            assert!(close(
+                direction(&fisheye, 1., 0.5).unwrap(),
+                Vec3::new(1., 0., 0.)
+            ));
+            assert!(direction(&fisheye, 0., 0.).is_none());
+        }
+
+        // the middle of each face looks right, left, up, down, forward and back
+        let cube = CubeMap::new(view);
+        let expected = [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(-1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., -1., 0.),
+            Vec3::new(0., 0., -1.),
+            Vec3::new(0., 0., 1.),
+        ];
+        for (face, expected) in expected.iter().enumerate() {
+            let s = (face as f64 + 0.5) / 6.;
+            assert!(close(direction(&cube, s, 0.5).unwrap(), *expected));
+        }
+    }
 }
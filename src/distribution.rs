@@ -0,0 +1,134 @@
+/*
+    Piecewise constant distributions for importance sampling, after pbrt's Distribution1D/2D.
+    A function is tabulated over [0, 1) (or [0, 1)^2) and sampled proportionally to its values.
+*/
+
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty());
+        let n = func.len() as f64;
+        let mut cdf = vec![0.; func.len() + 1];
+        for (i, f) in func.iter().enumerate() {
+            cdf[i + 1] = cdf[i] + f.max(0.) / n;
+        }
+        let integral = cdf[func.len()];
+
+        // an all black function falls back to uniform sampling
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0. {
+                *c / integral
+            } else {
+                i as f64 / n
+            };
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // index of the segment whose cdf range contains u
+    fn find_segment(&self, u: f64) -> usize {
+        let upper = self.cdf.partition_point(|c| *c <= u);
+        upper.clamp(1, self.count()) - 1
+    }
+
+    // point in [0, 1) and its density, plus the index of the segment it lies in
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let i = self.find_segment(u);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let offset = if width > 0. {
+            (u - self.cdf[i]) / width
+        } else {
+            0.
+        };
+        let x = ((i as f64 + offset) / self.count() as f64).min(1. - f64::EPSILON);
+        (x, self.pdf(x), i)
+    }
+
+    // segment index and its probability
+    pub fn sample_discrete(&self, u: f64) -> (usize, f64) {
+        let i = self.find_segment(u);
+        (i, self.discrete_pdf(i))
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let i = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.discrete_pdf(i) * self.count() as f64
+    }
+
+    pub fn discrete_pdf(&self, i: usize) -> f64 {
+        self.cdf[i + 1] - self.cdf[i]
+    }
+}
+
+// func is laid out row by row, nv rows of nu values, sampled as a marginal over v then a conditional over u
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        assert_eq!(func.len(), nu * nv);
+        let conditional: Vec<Distribution1D> = func
+            .chunks(nu)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    // (u, v) in [0, 1)^2 and its density
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_the_function() {
+        let distribution = Distribution1D::new(vec![1., 0., 3.]);
+        assert_eq!(distribution.sample_discrete(0.1), (0, 0.25));
+        assert_eq!(distribution.sample_discrete(0.5).0, 2);
+        let (x, pdf, i) = distribution.sample_continuous(0.625);
+        assert_eq!(i, 2);
+        assert!((x - 5. / 6.).abs() < 1e-12);
+        assert!((pdf - 2.25).abs() < 1e-12);
+
+        let image = Distribution2D::new(&[0., 0., 0., 8.], 2, 2);
+        let ((u, v), pdf) = image.sample(0.3, 0.7);
+        assert!(u >= 0.5 && v >= 0.5);
+        assert!((pdf - 4.).abs() < 1e-12);
+        assert_eq!(image.pdf(0.2, 0.2), 0.);
+    }
+}
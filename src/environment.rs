@@ -0,0 +1,126 @@
+use super::{
+    distribution::Distribution2D,
+    light::{Light, LightSample},
+    texture::{ColorSpace, ImageTexture, TextureError},
+    utils, Color, Direction, Position, Vec3,
+};
+use std::f64::consts::PI;
+
+/*
+    Equirectangular environment map, lighting the scene from infinitely far away.
+    Rays escaping the scene see it, and it gets sampled as a light proportionally to
+    the luminance of its texels, so small bright features like the sun are found quickly.
+    The layout matches the uv mapping of spheres: u goes around the y axis, the top row is straight up.
+*/
+
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+    // around the y axis, radians
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    // rotation in degrees around the y axis, intensity scales the radiance stored in the image
+    pub fn new(filename: &str, rotation: f64, intensity: f64) -> Result<Self, TextureError> {
+        let (width, height, texels) = ImageTexture::decode(filename, ColorSpace::Srgb)?;
+        Ok(EnvironmentMap::from_texels(
+            width as usize,
+            height as usize,
+            texels,
+            rotation,
+            intensity,
+        ))
+    }
+
+    pub fn from_texels(
+        width: usize,
+        height: usize,
+        texels: Vec<Color>,
+        rotation: f64,
+        intensity: f64,
+    ) -> Self {
+        assert_eq!(texels.len(), width * height);
+
+        // rows near the poles cover less solid angle
+        let func: Vec<f64> = texels
+            .iter()
+            .enumerate()
+            .map(|(i, texel)| {
+                let row = i / width;
+                let sin_theta = (PI * (row as f64 + 0.5) / height as f64).sin();
+                texel.luminance() * sin_theta
+            })
+            .collect();
+
+        EnvironmentMap {
+            distribution: Distribution2D::new(&func, width, height),
+            width,
+            height,
+            texels,
+            rotation: utils::degrees_to_radians(rotation),
+            intensity,
+        }
+    }
+
+    // s across the image from the left, t down the image from the top, both in [0, 1)
+    fn texel(&self, s: f64, t: f64) -> Color {
+        let i = ((s * self.width as f64) as usize).min(self.width - 1);
+        let j = ((t * self.height as f64) as usize).min(self.height - 1);
+        self.intensity * self.texels[j * self.width + i]
+    }
+
+    pub fn radiance(&self, direction: &Direction) -> Color {
+        let d = direction.normalize();
+        let theta = utils::clamp(-d.y(), -1., 1.).acos();
+        let phi = (-d.z()).atan2(d.x()) - self.rotation + PI;
+        let s = (phi / (2. * PI)).rem_euclid(1.);
+        let t = 1. - theta / PI;
+        self.texel(s, t)
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _p: &Position) -> Option<LightSample> {
+        let ((s, t), pdf) = self
+            .distribution
+            .sample(utils::random_double(), utils::random_double());
+        let theta = PI * (1. - t);
+        let phi = 2. * PI * s - PI + self.rotation;
+        let sin_theta = theta.sin();
+        if pdf <= 0. || sin_theta <= 0. {
+            return None;
+        }
+
+        // from the density over the image to the density over solid angle
+        let pdf = pdf / (2. * PI * PI * sin_theta);
+        Some(LightSample {
+            direction: Vec3::new(sin_theta * phi.cos(), -theta.cos(), -sin_theta * phi.sin()),
+            distance: f64::INFINITY,
+            irradiance: self.texel(s, t) / pdf,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_point_at_the_bright_texel() {
+        // a black map with one bright texel just above the horizon
+        let (width, height) = (8, 4);
+        let mut texels = vec![Color::new(0., 0., 0.); width * height];
+        texels[width + 2] = Color::new(10., 10., 10.);
+        let map = EnvironmentMap::from_texels(width, height, texels, 0., 1.);
+
+        for _ in 0..100 {
+            let sample = map.sample(&Vec3::new(0., 0., 0.)).unwrap();
+            assert!(sample.direction.y() > 0.);
+            assert!(map.radiance(&sample.direction).r() == 10.);
+        }
+    }
+}
@@ -1,10 +1,70 @@
-use super::{material::Material, Direction, Position, Ray, AABB};
+use super::{
+    light::Light,
+    material::Material,
+    ray::RayKind,
+    texture::{Footprint, TexturePoint},
+    utils, Direction, Position, Ray, AABB,
+};
 use std::ops::{Index, IndexMut};
 use std::sync::Arc;
 
+/*
+    Shapes that can be sampled also let emissive objects act as lights. sample_area picks a point
+    uniformly over the surface, sample_direction picks a direction from origin towards the shape
+    with its density over solid angle, and direction_pdf gives that density for any direction.
+    Shapes with a better strategy than converting area samples override the last two.
+    Shapes that move during the shutter, like MovingSphere, keep the zero area default:
+    light samples carry no time to place them at.
+*/
 pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
+    fn area(&self) -> f64 {
+        0.
+    }
+    // a point on the surface and its outward normal
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        None
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (point, normal) = self.sample_area()?;
+        area_to_solid_angle(origin, &point, &normal, self.area())
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let ray = Ray::new(*origin, *direction, None).with_kind(RayKind::Light);
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit_to_solid_angle(&hit, direction, self.area()),
+            None => 0.,
+        }
+    }
+}
+
+// unit direction towards a point sampled uniformly over an area, and its density over solid angle
+pub fn area_to_solid_angle(
+    origin: &Position,
+    point: &Position,
+    normal: &Direction,
+    area: f64,
+) -> Option<(Direction, f64)> {
+    let to_point = *point - *origin;
+    let distance_squared = to_point.squared_length();
+    let direction = to_point.normalize();
+    let cosine = normal.dot(direction).abs();
+    if area <= 0. || cosine < 1e-8 || distance_squared <= 0. {
+        return None;
+    }
+    Some((direction, distance_squared / (cosine * area)))
+}
+
+// density over solid angle of having reached the hit point by uniform area sampling
+pub fn hit_to_solid_angle(hit: &HitRecord, direction: &Direction, area: f64) -> f64 {
+    let length = direction.length();
+    let cosine = hit.normal.dot(*direction).abs() / length;
+    if area <= 0. || cosine < 1e-8 {
+        return 0.;
+    }
+    let distance = hit.t * length;
+    distance * distance / (cosine * area)
 }
 
 pub struct HitRecord {
@@ -12,9 +72,21 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub p: Position,
+    // the hit point in the space of the innermost object, before any instance transforms
+    pub object_p: Position,
     pub normal: Direction,
     pub front_face: bool,
+    // time of the incoming ray, lets textures animate
+    pub time: f64,
     pub material: Arc<dyn Material>,
+    // partial derivatives of p with respect to u and v, zero where the shape doesn't provide them
+    pub dpdu: Direction,
+    pub dpdv: Direction,
+    pub footprint: Option<Footprint>,
+    // the object is also in the light list, so its emission is found by direct lighting
+    pub sampled_as_light: bool,
+    // the only lights illuminating the object, all of them when None
+    pub light_links: Option<Arc<Vec<Arc<dyn Light>>>>,
 }
 
 impl HitRecord {
@@ -33,15 +105,117 @@ impl HitRecord {
             u,
             v,
             p,
+            object_p: p,
             front_face,
+            time: r.time(),
             normal: if front_face {
                 outward_normal
             } else {
                 -outward_normal
             },
             material: material.clone(),
+            dpdu: Direction::new(0., 0., 0.),
+            dpdv: Direction::new(0., 0., 0.),
+            footprint: None,
+            sampled_as_light: false,
+            light_links: None,
         }
     }
+
+    pub fn lit_by(&self, light: &dyn Light) -> bool {
+        match &self.light_links {
+            Some(links) => links.iter().any(|link| {
+                Arc::as_ptr(link) as *const () == light as *const dyn Light as *const ()
+            }),
+            None => true,
+        }
+    }
+
+    pub fn with_partials(mut self, dpdu: Direction, dpdv: Direction) -> Self {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    pub fn with_object_point(mut self, object_p: Position) -> Self {
+        self.object_p = object_p;
+        self
+    }
+
+    pub fn texture_point(&self) -> TexturePoint {
+        TexturePoint {
+            u: self.u,
+            v: self.v,
+            p: self.p,
+            object_p: self.object_p,
+            time: self.time,
+            footprint: self.footprint,
+        }
+    }
+
+    pub fn offset_points(&self, r: &Ray) -> Option<(Position, Position)> {
+        /*
+            where the differential rays meet the tangent plane at p
+        */
+        let differentials = r.differentials()?;
+        let d = self.normal.dot(self.p);
+        let tx = (d - self.normal.dot(differentials.rx_origin))
+            / self.normal.dot(differentials.rx_direction);
+        let ty = (d - self.normal.dot(differentials.ry_origin))
+            / self.normal.dot(differentials.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return None;
+        }
+
+        Some((
+            differentials.rx_origin + tx * differentials.rx_direction,
+            differentials.ry_origin + ty * differentials.ry_direction,
+        ))
+    }
+
+    pub fn compute_footprint(&self, r: &Ray) -> Option<Footprint> {
+        let (px, py) = self.offset_points(r)?;
+        let (dpdx, dpdy) = (px - self.p, py - self.p);
+
+        /*
+            dpdx = dpdu * dudx + dpdv * dvdx is overdetermined,
+            so it gets solved in the two dimensions the normal is least aligned with
+        */
+        let (nx, ny, nz) = (
+            self.normal.x().abs(),
+            self.normal.y().abs(),
+            self.normal.z().abs(),
+        );
+        let (d0, d1) = if nx > ny && nx > nz {
+            (1, 2)
+        } else if ny > nz {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+
+        let det = self.dpdu[d0] * self.dpdv[d1] - self.dpdv[d0] * self.dpdu[d1];
+        let solve = |b: Direction| {
+            if det.abs() < 1e-12 {
+                (0., 0.)
+            } else {
+                (
+                    (self.dpdv[d1] * b[d0] - self.dpdv[d0] * b[d1]) / det,
+                    (self.dpdu[d0] * b[d1] - self.dpdu[d1] * b[d0]) / det,
+                )
+            }
+        };
+        let ((dudx, dvdx), (dudy, dvdy)) = (solve(dpdx), solve(dpdy));
+
+        Some(Footprint {
+            dpdx,
+            dpdy,
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        })
+    }
 }
 
 pub struct HittableList {
@@ -76,6 +250,21 @@ impl IndexMut<usize> for HittableList {
     }
 }
 
+impl HittableList {
+    // an item chosen proportionally to its area
+    fn pick_by_area(&self) -> Option<&Arc<dyn Hittable>> {
+        let mut remaining = utils::random_double() * self.area();
+        for item in self.list.iter() {
+            let area = item.area();
+            if area > 0. && remaining < area {
+                return Some(item);
+            }
+            remaining -= area;
+        }
+        self.list.iter().rev().find(|item| item.area() > 0.)
+    }
+}
+
 impl Hittable for HittableList {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         /* */
@@ -109,4 +298,69 @@ impl Hittable for HittableList {
             None
         }
     }
+
+    fn area(&self) -> f64 {
+        self.list.iter().map(|item| item.area()).sum()
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.pick_by_area()?.sample_area()
+    }
+
+    // one item picked by area, the density is the mixture over all of them
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (direction, _) = self.pick_by_area()?.sample_direction(origin)?;
+        let pdf = self.direction_pdf(origin, &direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some((direction, pdf))
+    }
+
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let total = self.area();
+        if total <= 0. {
+            return 0.;
+        }
+        self.list
+            .iter()
+            .map(|item| item.area() / total * item.direction_pdf(origin, direction))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::ray::RayDifferentials;
+    use crate::rect::XZRect;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn footprint_of_a_rect_seen_from_above() {
+        let floor = XZRect::new(
+            0.,
+            10.,
+            0.,
+            20.,
+            0.,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let r = Ray::new(Vec3::new(5., 4., 5.), Vec3::new(0., -1., 0.), None).with_differentials(
+            Some(RayDifferentials {
+                rx_origin: Vec3::new(5., 4., 5.),
+                rx_direction: Vec3::new(0.01, -1., 0.),
+                ry_origin: Vec3::new(5., 4., 5.),
+                ry_direction: Vec3::new(0., -1., 0.02),
+            }),
+        );
+        let hit = floor.hit(&r, 0.001, f64::INFINITY).unwrap();
+        let footprint = hit.compute_footprint(&r).unwrap();
+
+        assert!((footprint.dudx - 0.04 / 10.).abs() < 1e-9);
+        assert!(footprint.dvdx.abs() < 1e-9);
+        assert!(footprint.dudy.abs() < 1e-9);
+        assert!((footprint.dvdy - 0.08 / 20.).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,294 @@
+use super::{utils, Direction};
+use std::{error::Error, f64::consts::PI, fmt, fs, io};
+
+/*
+    Measured luminous intensity of a luminaire, read from the IES LM-63 text format.
+    Only type C photometry is handled, the common one for architectural fixtures: the vertical
+    angle goes from the nadir (0) to the zenith (180), the horizontal angle around it.
+    Lookups take a direction in the luminaire's frame, with z along the nadir and x at
+    horizontal angle 0, and interpolate bilinearly between the measured angles.
+*/
+
+#[derive(Debug)]
+pub enum IesError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for IesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IesError::Io(e) => write!(f, "couldn't read ies profile: {}", e),
+            IesError::Parse(message) => write!(f, "couldn't parse ies profile: {}", message),
+        }
+    }
+}
+
+impl Error for IesError {}
+
+impl From<io::Error> for IesError {
+    fn from(e: io::Error) -> Self {
+        IesError::Io(e)
+    }
+}
+
+pub struct IesProfile {
+    // degrees, ascending
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    // candela for each horizontal angle, then each vertical angle
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+    // intensity averaged over the sphere of directions, candela
+    average_candela: f64,
+}
+
+// resolution of the integration over the sphere for the average intensity
+const AVERAGE_STEPS: usize = 128;
+
+impl IesProfile {
+    pub fn from_file(filename: &str) -> Result<Self, IesError> {
+        IesProfile::parse(&fs::read_to_string(filename)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, IesError> {
+        // the header holds keywords up to the tilt line, everything after is numbers
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find(|line| line.starts_with("TILT="))
+            .ok_or_else(|| IesError::Parse("missing TILT line".to_string()))?;
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| IesError::Parse(format!("not a number: {}", token)))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(IesError::Parse("unexpected end of file".to_string())))
+        };
+
+        // the lamp tilt only matters for lamps whose output changes with their orientation
+        if tilt == "TILT=INCLUDE" {
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as i32;
+        let _units = next()?;
+        let (_width, _length, _height) = (next()?, next()?, next()?);
+        let ballast_factor = next()?;
+        let ballast_lamp_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(IesError::Parse(
+                "only type C photometry is supported".to_string(),
+            ));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(IesError::Parse("no measured angles".to_string()));
+        }
+
+        let vertical = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<Vec<f64>, IesError>>()?;
+        let horizontal = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<Vec<f64>, IesError>>()?;
+        let scale = multiplier * ballast_factor * ballast_lamp_factor;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            candela.push(
+                (0..vertical_count)
+                    .map(|_| next().map(|c| scale * c))
+                    .collect::<Result<Vec<f64>, IesError>>()?,
+            );
+        }
+
+        Ok(IesProfile::new(vertical, horizontal, candela))
+    }
+
+    pub fn new(vertical: Vec<f64>, horizontal: Vec<f64>, candela: Vec<Vec<f64>>) -> Self {
+        let max_candela = candela.iter().flatten().fold(0., |max: f64, c| max.max(*c));
+        let mut profile = IesProfile {
+            vertical,
+            horizontal,
+            candela,
+            max_candela,
+            average_candela: 0.,
+        };
+
+        // midpoint rule over theta and phi, weighted by the solid angle of each cell
+        let (d_theta, d_phi) = (
+            PI / AVERAGE_STEPS as f64,
+            2. * PI / (2 * AVERAGE_STEPS) as f64,
+        );
+        let mut integral = 0.;
+        for i in 0..AVERAGE_STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..2 * AVERAGE_STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                integral += profile.candela_at(theta.to_degrees(), phi.to_degrees())
+                    * theta.sin()
+                    * d_theta
+                    * d_phi;
+            }
+        }
+        profile.average_candela = integral / (4. * PI);
+        profile
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    // total luminous flux of the luminaire
+    pub fn lumens(&self) -> f64 {
+        4. * PI * self.average_candela
+    }
+
+    // the horizontal angle folded into the measured range according to the symmetry of the data
+    fn fold_horizontal(&self, phi: f64) -> f64 {
+        let last = *self.horizontal.last().unwrap();
+        let phi = phi.rem_euclid(360.);
+        if self.horizontal.len() == 1 {
+            // rotationally symmetric
+            self.horizontal[0]
+        } else if (last - 90.).abs() < 1e-6 {
+            // symmetric in each quadrant
+            let phi = if phi > 180. { 360. - phi } else { phi };
+            if phi > 90. {
+                180. - phi
+            } else {
+                phi
+            }
+        } else if (last - 180.).abs() < 1e-6 {
+            // symmetric about the 0-180 plane
+            if phi > 180. {
+                360. - phi
+            } else {
+                phi
+            }
+        } else {
+            phi
+        }
+    }
+
+    // index of the segment containing x and how far along it x lies, None outside of the angles
+    fn segment(angles: &[f64], x: f64) -> Option<(usize, f64)> {
+        if angles.len() == 1 {
+            return Some((0, 0.));
+        }
+        if x < angles[0] || x > angles[angles.len() - 1] {
+            return None;
+        }
+        let i = angles
+            .partition_point(|a| *a <= x)
+            .clamp(1, angles.len() - 1)
+            - 1;
+        let width = angles[i + 1] - angles[i];
+        Some((
+            i,
+            if width > 0. {
+                (x - angles[i]) / width
+            } else {
+                0.
+            },
+        ))
+    }
+
+    // candela at a vertical and horizontal angle in degrees, zero where nothing was measured
+    pub fn candela_at(&self, theta: f64, phi: f64) -> f64 {
+        let (v, tv) = match IesProfile::segment(&self.vertical, theta) {
+            Some(segment) => segment,
+            None => return 0.,
+        };
+        let (h, th) = match IesProfile::segment(&self.horizontal, self.fold_horizontal(phi)) {
+            Some(segment) => segment,
+            None => return 0.,
+        };
+        let at = |h: usize, v: usize| {
+            let row = &self.candela[h.min(self.horizontal.len() - 1)];
+            row[v.min(self.vertical.len() - 1)]
+        };
+        let lerp = |h: usize| (1. - tv) * at(h, v) + tv * at(h, v + 1);
+        (1. - th) * lerp(h) + th * lerp(h + 1)
+    }
+
+    // candela towards a direction in the luminaire's frame, z being the nadir
+    pub fn candela(&self, local: &Direction) -> f64 {
+        let d = local.normalize();
+        let theta = utils::clamp(d.z(), -1., 1.).acos().to_degrees();
+        let phi = d.y().atan2(d.x()).to_degrees();
+        self.candela_at(theta, phi)
+    }
+
+    // intensity relative to the average over all directions, so a light keeps its total power
+    pub fn normalized(&self, local: &Direction) -> f64 {
+        if self.average_candela <= 0. {
+            return 0.;
+        }
+        self.candela(local) / self.average_candela
+    }
+
+    // intensity relative to the brightest direction
+    pub fn relative(&self, local: &Direction) -> f64 {
+        if self.max_candela <= 0. {
+            return 0.;
+        }
+        self.candela(local) / self.max_candela
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    #[test]
+    fn parses_and_interpolates_type_c_profiles() {
+        // a downlight with bilateral symmetry, nothing above the horizon
+        let text = "IESNA:LM-63-2002
+[TEST] synthetic
+[MANUFAC] none
+TILT=NONE
+1 1000 2 3 2 1 2 0.1 0.1 0
+1.0 1.0 20
+0 45 90
+0, 180
+100 50 0
+100 20 0
+";
+        let profile = IesProfile::parse(text).unwrap();
+        assert_eq!(profile.max_candela(), 200.);
+        assert!((profile.candela_at(0., 0.) - 200.).abs() < 1e-9);
+        // halfway between 0 and 45 degrees, and between the 0 and 180 degree planes
+        assert!((profile.candela_at(22.5, 90.) - 0.5 * (150. + 120.)).abs() < 1e-9);
+        // mirrored about the 0-180 plane
+        assert!((profile.candela_at(45., 270.) - profile.candela_at(45., 90.)).abs() < 1e-9);
+        assert_eq!(profile.candela_at(120., 0.), 0.);
+        assert_eq!(profile.candela(&Vec3::new(0., 0., -1.)), 0.);
+
+        // an isotropic source emits 4 pi times its intensity, and normalizes to one everywhere
+        let isotropic = IesProfile::new(vec![0., 180.], vec![0.], vec![vec![100., 100.]]);
+        assert!((isotropic.lumens() / (400. * PI) - 1.).abs() < 1e-3);
+        assert!((isotropic.normalized(&Vec3::new(1., 2., 3.)) - 1.).abs() < 1e-3);
+        assert!(profile.lumens() < isotropic.lumens());
+
+        assert!(IesProfile::parse("TILT=NONE\n1 1000 1 3").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::f64;
 
-use super::{utils, Arc, Direction, HitRecord, Hittable, Ray, Vec3, AABB};
+use super::{utils, Arc, Direction, HitRecord, Hittable, Position, Ray, Vec3, AABB};
 
 pub struct Translate {
     object: Arc<dyn Hittable>,
@@ -15,17 +15,22 @@ impl Translate {
 
 impl Hittable for Translate {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let moved_ray = Ray::new(r.origin() - self.offset, r.direction(), Some(r.time()));
+        let moved_ray =
+            Ray::new(r.origin() - self.offset, r.direction(), Some(r.time())).with_kind(r.kind());
         if let Some(hit) = self.object.hit(&moved_ray, t_min, t_max) {
-            return Some(HitRecord::new(
-                hit.t,
-                hit.u,
-                hit.v,
-                hit.p + self.offset,
-                hit.normal,
-                &moved_ray,
-                &hit.material,
-            ));
+            return Some(
+                HitRecord::new(
+                    hit.t,
+                    hit.u,
+                    hit.v,
+                    hit.p + self.offset,
+                    hit.normal,
+                    &moved_ray,
+                    &hit.material,
+                )
+                .with_partials(hit.dpdu, hit.dpdv)
+                .with_object_point(hit.object_p),
+            );
         }
         None
     }
@@ -40,6 +45,57 @@ impl Hittable for Translate {
             None
         }
     }
+
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let (point, normal) = self.object.sample_area()?;
+        Some((point + self.offset, normal))
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.object.sample_direction(&(*origin - self.offset))
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.object
+            .direction_pdf(&(*origin - self.offset), direction)
+    }
+}
+
+// swaps which side of the object counts as the front, e.g. to point a one-sided light downwards
+pub struct FlipFace {
+    object: Arc<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        FlipFace { object }
+    }
+}
+
+impl Hittable for FlipFace {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.object.hit(r, t_min, t_max).map(|mut hit| {
+            hit.front_face = !hit.front_face;
+            hit
+        })
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.object.bounding_box(t0, t1)
+    }
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.object.sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.object.sample_direction(origin)
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.object.direction_pdf(origin, direction)
+    }
 }
 
 pub struct RotateY {
@@ -89,6 +145,22 @@ impl RotateY {
             bbox: Some(AABB::new(min, max)),
         }
     }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -103,22 +175,23 @@ impl Hittable for RotateY {
         direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
         direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
 
-        let rotated_ray = Ray::new(origin, direction, Some(r.time()));
+        let rotated_ray = Ray::new(origin, direction, Some(r.time())).with_kind(r.kind());
         if let Some(hit) = self.object.hit(&rotated_ray, t_min, t_max) {
             let (mut p, mut normal) = (hit.p, hit.normal);
             p[0] = self.cos_theta * hit.p[0] + self.sin_theta * hit.p[2];
             p[2] = -self.sin_theta * hit.p[0] + self.cos_theta * hit.p[2];
             normal[0] = self.cos_theta * hit.normal[0] + self.sin_theta * hit.normal[2];
             normal[2] = -self.sin_theta * hit.normal[0] + self.cos_theta * hit.normal[2];
-            Some(HitRecord::new(
-                hit.t,
-                hit.u,
-                hit.v,
-                p,
-                normal,
-                &rotated_ray,
-                &hit.material,
-            ))
+            let (mut dpdu, mut dpdv) = (hit.dpdu, hit.dpdv);
+            dpdu[0] = self.cos_theta * hit.dpdu[0] + self.sin_theta * hit.dpdu[2];
+            dpdu[2] = -self.sin_theta * hit.dpdu[0] + self.cos_theta * hit.dpdu[2];
+            dpdv[0] = self.cos_theta * hit.dpdv[0] + self.sin_theta * hit.dpdv[2];
+            dpdv[2] = -self.sin_theta * hit.dpdv[0] + self.cos_theta * hit.dpdv[2];
+            Some(
+                HitRecord::new(hit.t, hit.u, hit.v, p, normal, &rotated_ray, &hit.material)
+                    .with_partials(dpdu, dpdv)
+                    .with_object_point(hit.object_p),
+            )
         } else {
             None
         }
@@ -126,4 +199,19 @@ impl Hittable for RotateY {
     fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
         self.bbox
     }
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let (point, normal) = self.object.sample_area()?;
+        Some((self.to_world(point), self.to_world(normal)))
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (direction, pdf) = self.object.sample_direction(&self.to_object(*origin))?;
+        Some((self.to_world(direction), pdf))
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.object
+            .direction_pdf(&self.to_object(*origin), &self.to_object(*direction))
+    }
 }
@@ -0,0 +1,614 @@
+use super::{
+    distribution::Distribution1D,
+    ies::IesProfile,
+    light_tree::{DirectionCone, LightBounds, LightTree},
+    material::Power,
+    onb::Onb,
+    ray::RayKind,
+    texture::{Texture, TexturePoint},
+    utils, Arc, Color, Direction, HitRecord, Hittable, Position, Ray, Vec3, AABB,
+};
+use std::{f64::consts::PI, sync::OnceLock};
+
+/*
+    Lights the integrator samples explicitly with shadow rays. Delta lights are infinitely small
+    or infinitely far away, so rays never hit them and sampling them is the only way they contribute.
+*/
+
+pub struct LightSample {
+    // unit vector from the shaded point towards the light
+    pub direction: Direction,
+    // shadow rays only need to reach this far
+    pub distance: f64,
+    // irradiance arriving at the shaded point on a surface facing the light
+    pub irradiance: Color,
+}
+
+pub trait Light: Send + Sync {
+    fn sample(&self, p: &Position) -> Option<LightSample>;
+    // where the light is, how much it emits and which way, None for lights infinitely far away
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+// how direct lighting picks the one light it samples at each hit
+#[derive(Copy, Clone)]
+pub enum LightSelection {
+    Uniform,
+    // proportionally to the emitted power
+    Power,
+    // with a light BVH, accounting for distance and orientation to the shaded point
+    Tree,
+}
+
+enum Selector {
+    Uniform,
+    Power(Distribution1D),
+    Tree(LightTree),
+}
+
+pub struct LightList {
+    list: Vec<Arc<dyn Light>>,
+    selection: LightSelection,
+    // built on the first sample, once all lights are in
+    selector: OnceLock<(Vec<usize>, Vec<usize>, Selector)>,
+}
+
+impl LightList {
+    pub fn new(list: Vec<Arc<dyn Light>>) -> Self {
+        LightList {
+            list,
+            selection: LightSelection::Tree,
+            selector: OnceLock::new(),
+        }
+    }
+    pub fn with_selection(mut self, selection: LightSelection) -> Self {
+        self.selection = selection;
+        self.selector = OnceLock::new();
+        self
+    }
+    pub fn add(&mut self, light: Arc<dyn Light>) {
+        self.list.push(light);
+        self.selector = OnceLock::new();
+    }
+    pub fn list(&self) -> &Vec<Arc<dyn Light>> {
+        &self.list
+    }
+
+    // indices of the infinite and the bounded lights, and how to choose among the bounded ones
+    fn selector(&self) -> &(Vec<usize>, Vec<usize>, Selector) {
+        self.selector.get_or_init(|| {
+            let (mut infinite, mut bounded) = (vec![], vec![]);
+            for (i, light) in self.list.iter().enumerate() {
+                match light.bounds() {
+                    Some(bounds) => bounded.push((i, bounds)),
+                    None => infinite.push(i),
+                }
+            }
+            let indices = bounded.iter().map(|(i, _)| *i).collect();
+            let selector = match self.selection {
+                LightSelection::Uniform => Selector::Uniform,
+                LightSelection::Power if !bounded.is_empty() => Selector::Power(
+                    Distribution1D::new(bounded.iter().map(|(_, b)| b.phi).collect()),
+                ),
+                LightSelection::Power => Selector::Uniform,
+                LightSelection::Tree => Selector::Tree(LightTree::new(bounded)),
+            };
+            (infinite, indices, selector)
+        })
+    }
+
+    /*
+        one light for the point p and the probability of having picked it.
+        infinite lights get the same chance as the bounded lights taken together
+    */
+    pub fn sample(&self, p: &Position) -> Option<(&Arc<dyn Light>, f64)> {
+        let (infinite, bounded, selector) = self.selector();
+        let groups = infinite.len() + if bounded.is_empty() { 0 } else { 1 };
+        if groups == 0 {
+            return None;
+        }
+        let p_infinite = infinite.len() as f64 / groups as f64;
+
+        let u = utils::random_double();
+        if u < p_infinite {
+            let i = ((u / p_infinite * infinite.len() as f64) as usize).min(infinite.len() - 1);
+            return Some((&self.list[infinite[i]], 1. / groups as f64));
+        }
+
+        let (index, probability) = match selector {
+            Selector::Uniform => {
+                let i = utils::random_int(0, bounded.len() as i32) as usize;
+                (bounded[i], 1. / bounded.len() as f64)
+            }
+            Selector::Power(distribution) => {
+                let (i, probability) = distribution.sample_discrete(utils::random_double());
+                (bounded[i], probability)
+            }
+            Selector::Tree(tree) => tree.sample(p)?,
+        };
+        if probability <= 0. {
+            return None;
+        }
+        Some((&self.list[index], (1. - p_infinite) * probability))
+    }
+}
+
+// the color only sets the hue, the brightness comes from the physical quantity. black stays black
+fn normalized(color: Color) -> Color {
+    if color.luminance() > 0. {
+        color / color.luminance()
+    } else {
+        Color::new(0., 0., 0.)
+    }
+}
+
+pub enum Irradiance {
+    WattsPerSquareMeter(f64),
+    Lux(f64),
+}
+
+impl Irradiance {
+    fn watts_per_square_meter(&self) -> f64 {
+        match self {
+            Irradiance::WattsPerSquareMeter(irradiance) => *irradiance,
+            Irradiance::Lux(lux) => lux / Power::LUMENS_PER_WATT,
+        }
+    }
+}
+
+pub struct PointLight {
+    position: Position,
+    // radiant intensity, W/sr
+    intensity: Color,
+    // angular distribution, oriented with the nadir of the profile along w
+    profile: Option<(Arc<IesProfile>, Onb)>,
+}
+
+impl PointLight {
+    // the power gets emitted uniformly in all directions
+    pub fn new(position: Position, color: Color, power: Power) -> Self {
+        PointLight {
+            position,
+            intensity: power.watts() / (4. * PI) * normalized(color),
+            profile: None,
+        }
+    }
+    // shapes the emission with a measured profile aimed at target, keeping the total power
+    pub fn with_profile(mut self, profile: Arc<IesProfile>, target: Position) -> Self {
+        self.profile = Some((profile, Onb::from_w(target - self.position)));
+        self
+    }
+    // a luminaire as measured, emitting the luminous flux of its profile
+    pub fn from_ies(
+        position: Position,
+        target: Position,
+        color: Color,
+        profile: Arc<IesProfile>,
+    ) -> Self {
+        PointLight::new(position, color, Power::Lumens(profile.lumens()))
+            .with_profile(profile, target)
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Position) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance = to_light.length();
+        let scale = match &self.profile {
+            Some((profile, frame)) => profile.normalized(&frame.to_local(-to_light)),
+            None => 1.,
+        };
+        if scale <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: scale * self.intensity / (distance * distance),
+        })
+    }
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            AABB::new(self.position, self.position),
+            4. * PI * self.intensity.luminance(),
+            DirectionCone::entire_sphere(),
+            0.,
+            false,
+        ))
+    }
+}
+
+pub struct SpotLight {
+    position: Position,
+    direction: Direction,
+    cos_inner: f64,
+    cos_outer: f64,
+    intensity: Color,
+    // texture projected through the cone, filling the outer cone's square
+    gobo: Option<Arc<dyn Texture>>,
+    frame: Onb,
+}
+
+impl SpotLight {
+    /*
+        full intensity inside the inner cone, smoothly falling off to zero at the outer cone.
+        angles are the half angles of the cones in degrees
+    */
+    pub fn new(
+        position: Position,
+        target: Position,
+        color: Color,
+        power: Power,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let cos_outer = utils::degrees_to_radians(outer_angle).cos();
+        let cos_inner = utils::degrees_to_radians(inner_angle.min(outer_angle)).cos();
+        // solid angle of the cone, with the falloff region counted as half lit
+        let solid_angle = 2. * PI * (1. - 0.5 * (cos_inner + cos_outer));
+        SpotLight {
+            position,
+            direction: (target - position).normalize(),
+            cos_inner,
+            cos_outer,
+            intensity: power.watts() / solid_angle * normalized(color),
+            gobo: None,
+            frame: Onb::from_w(target - position),
+        }
+    }
+
+    pub fn with_gobo(mut self, gobo: Arc<dyn Texture>) -> Self {
+        self.gobo = Some(gobo);
+        self
+    }
+
+    // color of the gobo in the direction going out of the light, the image's u axis stays horizontal
+    fn gobo(&self, p: &Position, outgoing: &Direction) -> Color {
+        let gobo = match &self.gobo {
+            Some(gobo) => gobo,
+            None => return Color::new_diagonal(1.),
+        };
+        let local = self.frame.to_local(*outgoing);
+        let tan_outer = (1. - self.cos_outer * self.cos_outer).max(0.).sqrt() / self.cos_outer;
+        let (u, v) = (
+            0.5 + 0.5 * local.x() / (local.z() * tan_outer),
+            0.5 + 0.5 * local.y() / (local.z() * tan_outer),
+        );
+        gobo.sample(&TexturePoint::new(u, v, *p))
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3. - 2. * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Position) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff <= 0. {
+            return None;
+        }
+        let gobo = self.gobo(p, &-direction);
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: falloff * gobo * self.intensity / (distance * distance),
+        })
+    }
+    // the falloff region counts as emission spreading around the inner cone
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            AABB::new(self.position, self.position),
+            4. * PI * self.intensity.luminance(),
+            DirectionCone::new(self.direction, self.cos_inner),
+            (self.cos_outer.acos() - self.cos_inner.acos()).cos(),
+            false,
+        ))
+    }
+}
+
+// parallel light from infinitely far away, like the sun
+pub struct DirectionalLight {
+    // towards the light
+    direction: Direction,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    // direction points towards the light, the irradiance is measured perpendicular to it
+    pub fn new(direction: Direction, color: Color, irradiance: Irradiance) -> Self {
+        DirectionalLight {
+            direction: direction.normalize(),
+            irradiance: irradiance.watts_per_square_meter() * normalized(color),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Position) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+/*
+    Emissive object sampled over the solid angle it covers. It goes into both the world and
+    the light list: its hits are marked so the integrator doesn't count the emission twice.
+*/
+pub struct AreaLight {
+    object: Arc<dyn Hittable>,
+    bounds: LightBounds,
+}
+
+// points over the surface used to estimate the power and orientation of an area light
+const AREA_LIGHT_BOUNDS_SAMPLES: usize = 16;
+
+impl AreaLight {
+    // the object has to be one that can be sampled, with an area
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        assert!(
+            object.area() > 0.,
+            "Area lights need an object that can be sampled, moving spheres and media can't"
+        );
+        AreaLight {
+            bounds: AreaLight::estimate_bounds(object.as_ref()),
+            object,
+        }
+    }
+
+    /*
+        emitted radiance looked at from both sides of points sampled over the surface.
+        a flat emitter keeps its normal, anything else is bounded as emitting in all directions
+    */
+    fn estimate_bounds(object: &dyn Hittable) -> LightBounds {
+        let bbox = object
+            .bounding_box(0., 1.)
+            .unwrap_or_else(|| AABB::new(Vec3::new_diagonal(0.), Vec3::new_diagonal(0.)));
+        let offset = 1e-3 * (bbox.max() - bbox.min()).length().max(1e-3);
+        let emitted = |point: Position, side: Direction| {
+            let ray = Ray::new(point + offset * side, -side, None).with_kind(RayKind::Light);
+            object
+                .hit(&ray, 0., 2. * offset)
+                .map_or(0., |hit| hit.material.emitted(&ray, &hit).luminance())
+        };
+
+        let (mut front, mut back, mut count) = (0., 0., 0);
+        let mut normal: Option<Direction> = None;
+        let mut flat = true;
+        for _ in 0..AREA_LIGHT_BOUNDS_SAMPLES {
+            let (point, n) = match object.sample_area() {
+                Some(sample) => sample,
+                None => break,
+            };
+            front += emitted(point, n);
+            back += emitted(point, -n);
+            count += 1;
+            match normal {
+                Some(first) if first.dot(n) < 1. - 1e-6 => flat = false,
+                Some(_) => (),
+                None => normal = Some(n),
+            }
+        }
+        if count == 0 {
+            return LightBounds::new(bbox, 0., DirectionCone::entire_sphere(), 0., false);
+        }
+
+        let (front, back) = (front / count as f64, back / count as f64);
+        let phi = PI * object.area() * (front + back);
+        let normals = match normal {
+            Some(n) if flat && back <= 0. => DirectionCone::new(n, 1.),
+            Some(n) if flat && front <= 0. => DirectionCone::new(-n, 1.),
+            Some(n) if flat => DirectionCone::new(n, 1.),
+            _ => DirectionCone::entire_sphere(),
+        };
+        LightBounds::new(bbox, phi, normals, 0., flat && front > 0. && back > 0.)
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, p: &Position) -> Option<LightSample> {
+        let (direction, pdf) = self.object.sample_direction(p)?;
+        if pdf <= 0. {
+            return None;
+        }
+        let ray = Ray::new(*p, direction, None).with_kind(RayKind::Light);
+        let hit = self.object.hit(&ray, 0.001, f64::INFINITY)?;
+        let radiance = hit.material.emitted(&ray, &hit);
+        Some(LightSample {
+            direction,
+            distance: hit.t,
+            irradiance: radiance / pdf,
+        })
+    }
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(self.bounds)
+    }
+}
+
+impl Hittable for AreaLight {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.object.hit(r, t_min, t_max).map(|mut hit| {
+            hit.sampled_as_light = true;
+            hit
+        })
+    }
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.object.bounding_box(t0, t1)
+    }
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.object.sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.object.sample_direction(origin)
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.object.direction_pdf(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::DiffuseLight, texture::Solid, MovingSphere, Sphere, Translate, Triangle, Vec3,
+        XYRect,
+    };
+
+    #[test]
+    fn spot_light_cone() {
+        let spot = SpotLight::new(
+            Vec3::new(0., 2., 0.),
+            Vec3::new(0., 0., 0.),
+            Vec3::new_diagonal(1.),
+            Power::Watts(100.),
+            20.,
+            30.,
+        );
+        let center = spot.sample(&Vec3::new(0., 0., 0.)).unwrap();
+        assert!((center.direction.y() - 1.).abs() < 1e-12);
+        assert!((center.distance - 2.).abs() < 1e-12);
+
+        // tan(25 degrees) * 2 lands within the falloff, tan(35 degrees) * 2 outside of the cone
+        let edge = spot.sample(&Vec3::new(0.93, 0., 0.)).unwrap();
+        assert!(edge.irradiance.r() < center.irradiance.r());
+        assert!(spot.sample(&Vec3::new(1.4, 0., 0.)).is_none());
+
+        // a black light emits nothing rather than nan
+        let black = PointLight::new(
+            Vec3::new(0., 2., 0.),
+            Vec3::new(0., 0., 0.),
+            Power::Watts(100.),
+        );
+        let sample = black.sample(&Vec3::new(0., 0., 0.)).unwrap();
+        assert_eq!(sample.irradiance.r(), 0.);
+        assert_eq!(black.bounds().unwrap().phi, 0.);
+    }
+
+    #[test]
+    fn area_lights_integrate_their_solid_angle() {
+        let white = Arc::new(DiffuseLight::new(Arc::new(Solid::color_vec3(
+            Vec3::new_diagonal(1.),
+        ))));
+        let origin = Vec3::new(0., 0., 0.);
+
+        // a 2x2 square one unit away covers 4 asin(1/2) steradians
+        let square = AreaLight::new(Arc::new(XYRect::new(-1., 1., -1., 1., 1., white.clone())));
+        let sphere = AreaLight::new(Arc::new(Sphere::new(
+            Vec3::new(0., 0., -4.),
+            2.,
+            white.clone(),
+        )));
+        let sphere_solid_angle = 2. * PI * (1. - 0.75f64.sqrt());
+        for _ in 0..100 {
+            let sample = square.sample(&origin).unwrap();
+            assert!((sample.irradiance.r() - 2. * PI / 3.).abs() < 1e-6);
+            let sample = sphere.sample(&origin).unwrap();
+            assert!((sample.irradiance.r() - sphere_solid_angle).abs() < 1e-6);
+        }
+
+        // the density of a sampled direction matches the one looked up afterwards
+        let triangle = Translate::new(
+            Arc::new(Triangle::new(
+                [
+                    Vec3::new(0., 0., 0.),
+                    Vec3::new(1., 0., 0.),
+                    Vec3::new(0., 1., 0.),
+                ],
+                white,
+            )),
+            Vec3::new(0., 0., 2.),
+        );
+        for _ in 0..100 {
+            let (direction, pdf) = triangle.sample_direction(&origin).unwrap();
+            assert!((triangle.direction_pdf(&origin, &direction) - pdf).abs() < 1e-6 * pdf);
+        }
+    }
+
+    #[test]
+    fn light_selection_is_unbiased() {
+        let p = Vec3::new(0., 0., 0.);
+        let point = |x: f64, watts: f64| -> Arc<dyn Light> {
+            Arc::new(PointLight::new(
+                Vec3::new(x, 1., 0.),
+                Vec3::new_diagonal(1.),
+                Power::Watts(watts),
+            ))
+        };
+        // pointing up, away from p, so it can't light it
+        let away: Arc<dyn Light> = Arc::new(SpotLight::new(
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 2., 0.),
+            Vec3::new_diagonal(1.),
+            Power::Watts(10.),
+            20.,
+            30.,
+        ));
+
+        for selection in [
+            LightSelection::Uniform,
+            LightSelection::Power,
+            LightSelection::Tree,
+        ] {
+            let lights = LightList::new(vec![
+                point(1., 10.),
+                point(-1.5, 20.),
+                point(2., 15.),
+                away.clone(),
+            ])
+            .with_selection(selection);
+
+            // weighting every pick by one over its probability counts the lights that can contribute
+            let n = 100_000;
+            let mut count = 0.;
+            for _ in 0..n {
+                if let Some((light, probability)) = lights.sample(&p) {
+                    if light.sample(&p).is_some() {
+                        count += 1. / probability;
+                    }
+                }
+            }
+            assert!((count / n as f64 - 3.).abs() < 0.2);
+        }
+
+        // the tree never picks the spot light facing away
+        let tree = LightList::new(vec![point(1., 10.), away]);
+        for _ in 0..100 {
+            let (_, probability) = tree.sample(&p).unwrap();
+            assert_eq!(probability, 1.);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn moving_spheres_cant_be_area_lights() {
+        let white = Arc::new(DiffuseLight::new(Arc::new(Solid::color_vec3(
+            Vec3::new_diagonal(1.),
+        ))));
+        AreaLight::new(Arc::new(MovingSphere::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            1.,
+            white,
+            0.,
+            1.,
+        )));
+    }
+}
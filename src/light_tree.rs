@@ -0,0 +1,277 @@
+use super::{utils, Direction, Position, Vec3, AABB};
+use std::{cmp::Ordering, f64::consts::PI};
+
+/*
+    Picking one light out of many, proportionally to an estimate of how much it contributes
+    at the shaded point. Lights are bounded by a box, their power and a cone of emitted directions,
+    after pbrt-v4's light BVH (Conty Estevez & Kulla - Importance Sampling of Many Lights with
+    Adaptive Tree Splitting). Nodes merge the bounds of their children, so a whole subtree far away
+    or facing the other way gets skipped at once.
+*/
+
+// the directions within angle acos(cos_theta) of w
+#[derive(Copy, Clone)]
+pub struct DirectionCone {
+    pub w: Direction,
+    pub cos_theta: f64,
+}
+
+impl DirectionCone {
+    pub fn new(w: Direction, cos_theta: f64) -> Self {
+        DirectionCone {
+            w: w.normalize(),
+            cos_theta,
+        }
+    }
+
+    pub fn entire_sphere() -> Self {
+        DirectionCone::new(Vec3::new(0., 0., 1.), -1.)
+    }
+
+    // smallest cone containing both
+    pub fn union(a: DirectionCone, b: DirectionCone) -> Self {
+        let theta_a = utils::clamp(a.cos_theta, -1., 1.).acos();
+        let theta_b = utils::clamp(b.cos_theta, -1., 1.).acos();
+        let theta_d = utils::clamp(a.w.dot(b.w), -1., 1.).acos();
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return a;
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return b;
+        }
+
+        let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+        let axis = a.w.cross(b.w);
+        if theta_o >= PI || axis.squared_length() == 0. {
+            return DirectionCone::entire_sphere();
+        }
+        // rotate a's axis towards b's until it sits in the middle of the merged cone
+        let (k, theta_r) = (axis.normalize(), theta_o - theta_a);
+        let w = a.w * theta_r.cos()
+            + k.cross(a.w) * theta_r.sin()
+            + k * k.dot(a.w) * (1. - theta_r.cos());
+        DirectionCone::new(w, theta_o.cos())
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct LightBounds {
+    pub bounds: AABB,
+    // emitted power, only compared between lights
+    pub phi: f64,
+    // normals of the emitting surface
+    pub normals: DirectionCone,
+    // how far from a normal light still leaves, pi/2 for diffuse surfaces
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+// cos(max(0, a - b)) and sin(max(0, a - b)) from the sines and cosines of a and b
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+impl LightBounds {
+    pub fn new(
+        bounds: AABB,
+        phi: f64,
+        normals: DirectionCone,
+        cos_theta_e: f64,
+        two_sided: bool,
+    ) -> Self {
+        LightBounds {
+            bounds,
+            phi,
+            normals,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    fn centroid(&self) -> Position {
+        0.5 * (self.bounds.min() + self.bounds.max())
+    }
+
+    pub fn union(a: &LightBounds, b: &LightBounds) -> Self {
+        if a.phi <= 0. {
+            return *b;
+        }
+        if b.phi <= 0. {
+            return *a;
+        }
+        LightBounds {
+            bounds: AABB::surrounding_box(a.bounds, b.bounds),
+            phi: a.phi + b.phi,
+            normals: DirectionCone::union(a.normals, b.normals),
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+
+    // conservative estimate of the light arriving at p, zero if none of it can
+    pub fn importance(&self, p: &Position) -> f64 {
+        if self.phi <= 0. {
+            return 0.;
+        }
+        let center = self.centroid();
+        let radius = 0.5 * (self.bounds.max() - self.bounds.min()).length();
+        let to_p = *p - center;
+        // don't let the estimate blow up for points within the bounds
+        let distance_squared = to_p.squared_length().max(radius);
+
+        let mut cos_theta_w = if to_p.squared_length() > 0. {
+            self.normals.w.dot(to_p.normalize())
+        } else {
+            1.
+        };
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = (1. - cos_theta_w * cos_theta_w).max(0.).sqrt();
+
+        // the angle the bounds subtend from p
+        let cos_theta_b = if to_p.squared_length() <= radius * radius {
+            -1.
+        } else {
+            (1. - radius * radius / to_p.squared_length())
+                .max(0.)
+                .sqrt()
+        };
+        let sin_theta_b = (1. - cos_theta_b * cos_theta_b).max(0.).sqrt();
+
+        let cos_theta_o = self.normals.cos_theta;
+        let sin_theta_o = (1. - cos_theta_o * cos_theta_o).max(0.).sqrt();
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.;
+        }
+
+        self.phi * cos_theta_p / distance_squared
+    }
+}
+
+enum LightNode {
+    Leaf {
+        bounds: LightBounds,
+        // index into the light list
+        light: usize,
+    },
+    Interior {
+        bounds: LightBounds,
+        left: Box<LightNode>,
+        right: Box<LightNode>,
+    },
+}
+
+impl LightNode {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            LightNode::Leaf { bounds, .. } => bounds,
+            LightNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// bounded lights only, the ones infinitely far away are picked separately
+pub struct LightTree {
+    root: Option<LightNode>,
+}
+
+impl LightTree {
+    // pairs of an index into the light list and the bounds of that light
+    pub fn new(lights: Vec<(usize, LightBounds)>) -> Self {
+        LightTree {
+            root: if lights.is_empty() {
+                None
+            } else {
+                Some(LightTree::build(lights))
+            },
+        }
+    }
+
+    // splits at the median centroid along the axis the centroids spread the most
+    fn build(mut lights: Vec<(usize, LightBounds)>) -> LightNode {
+        if lights.len() == 1 {
+            let (light, bounds) = lights[0];
+            return LightNode::Leaf { bounds, light };
+        }
+
+        let (mut min, mut max) = (
+            Vec3::new_diagonal(f64::INFINITY),
+            Vec3::new_diagonal(f64::NEG_INFINITY),
+        );
+        for (_, bounds) in lights.iter() {
+            let c = bounds.centroid();
+            for axis in 0..3 {
+                min[axis] = min[axis].min(c[axis]);
+                max[axis] = max[axis].max(c[axis]);
+            }
+        }
+        let extent = max - min;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        };
+        lights.sort_by(|a, b| {
+            a.1.centroid()[axis]
+                .partial_cmp(&b.1.centroid()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let right = LightTree::build(lights.split_off(lights.len() / 2));
+        let left = LightTree::build(lights);
+        LightNode::Interior {
+            bounds: LightBounds::union(left.bounds(), right.bounds()),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    // descends choosing children by importance, returns the light index and its probability
+    pub fn sample(&self, p: &Position) -> Option<(usize, f64)> {
+        let mut node = self.root.as_ref()?;
+        let mut probability = 1.;
+        loop {
+            match node {
+                LightNode::Leaf { bounds, light } => {
+                    return if bounds.importance(p) > 0. {
+                        Some((*light, probability))
+                    } else {
+                        None
+                    };
+                }
+                LightNode::Interior { left, right, .. } => {
+                    let (l, r) = (left.bounds().importance(p), right.bounds().importance(p));
+                    if l + r <= 0. {
+                        return None;
+                    }
+                    let p_left = l / (l + r);
+                    if utils::random_double() < p_left {
+                        probability *= p_left;
+                        node = left;
+                    } else {
+                        probability *= 1. - p_left;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+}
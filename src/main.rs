@@ -11,68 +11,231 @@ use std::time::Instant;
 use std::{env, sync::Mutex};
 
 mod aabb;
+mod background;
 mod bvh;
 mod camera;
+mod distribution;
+mod environment;
 mod hittable;
+mod ies;
 mod instance_transforms;
+mod light;
+mod light_tree;
 mod material;
+mod nodes;
+mod onb;
 mod perlin;
 mod pixel;
 mod plane;
+mod procedural;
 mod pyramid;
 mod ray;
 mod rect;
 mod scenes;
+mod simplex;
+mod sky;
+mod spectrum;
 mod sphere;
 mod texture;
 mod triangle;
 mod utils;
 mod vec;
+mod visibility;
 mod volume;
+mod worley;
 
 use aabb::AABB;
+use background::Background;
 use bvh::BVHNode;
-use camera::Camera;
+use camera::{Camera, CameraModel};
 use hittable::{HitRecord, Hittable, HittableList};
 use image::{imageops, ImageFormat, Rgb, RgbImage};
-use instance_transforms::{RotateY, Translate};
+use instance_transforms::{FlipFace, RotateY, Translate};
+use light::LightList;
 use material::Material;
 use perlin::Perlin;
 use plane::Plane;
 use pyramid::Pyramid;
-use ray::Ray;
+use ray::{Ray, RayKind};
 use rect::{Cuboid, XYRect, XZRect, YZRect};
+use spectrum::{Spectrum, Wavelengths};
 use sphere::{MovingSphere, Sphere};
 use texture::Texture;
 use triangle::Triangle;
 use vec::{Color, Direction, Position, Vec3};
+use worley::Worley;
 
-fn color<T: Hittable>(r: &Ray, world: &Arc<T>, background: &Color, depth: i32) -> Color {
+/*
+    light reaching the hit point, each sample tested with a shadow ray. one light gets picked
+    out of the light list and weighted by the chance of picking it, the background is always sampled
+*/
+fn direct_lighting<T: Hittable>(
+    r: &Ray,
+    hit: &HitRecord,
+    world: &Arc<T>,
+    lights: &LightList,
+    background: &dyn Background,
+) -> Color {
+    let mut direct = Color::new(0., 0., 0.);
+    let lights = lights
+        .sample(&hit.p)
+        .map(|(light, probability)| (light.as_ref(), 1. / probability))
+        .into_iter()
+        .chain(background.as_light().map(|light| (light, 1.)));
+    for (light, weight) in lights {
+        if !hit.lit_by(light) {
+            continue;
+        }
+        let sample = match light.sample(&hit.p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let f = match hit.material.evaluate(r, hit, &sample.direction) {
+            Some(f) if !f.near_zero() => f,
+            _ => continue,
+        };
+        let shadow_ray =
+            Ray::new(hit.p, sample.direction, Some(r.time())).with_kind(RayKind::Shadow);
+        if world
+            .hit(&shadow_ray, 0.001, sample.distance - 0.001)
+            .is_none()
+        {
+            direct += weight * f * sample.irradiance;
+        }
+    }
+
+    direct
+}
+
+/*
+    radiance of a ray escaping the scene. after a bounce off a material that samples lights,
+    a background that is also a light was already accounted for by direct_lighting
+*/
+fn escaped(r: &Ray, background: &dyn Background, lights_sampled: bool) -> Color {
+    if lights_sampled && background.as_light().is_some() {
+        Color::new(0., 0., 0.)
+    } else {
+        background.value(&r.direction())
+    }
+}
+
+// emission of the hit surface, unless direct_lighting already sampled it at the previous bounce
+fn emission(r: &Ray, hit: &HitRecord, lights_sampled: bool) -> Color {
+    if lights_sampled && hit.sampled_as_light {
+        Color::new(0., 0., 0.)
+    } else {
+        hit.material.emitted(r, hit)
+    }
+}
+
+fn color<T: Hittable>(
+    r: &Ray,
+    world: &Arc<T>,
+    lights: &LightList,
+    background: &dyn Background,
+    depth: i32,
+    lights_sampled: bool,
+) -> Color {
     if depth <= 0 {
         return Color::new(0., 0., 0.);
     }
-    if let Some(hit) = world.hit(r, 0.001, f64::INFINITY) {
-        let emitted = hit.material.emitted(hit.u, hit.v, &hit.p);
+    if let Some(mut hit) = world.hit(r, 0.001, f64::INFINITY) {
+        hit.footprint = hit.compute_footprint(r);
+        let samples_lights = hit.material.samples_lights();
+        let mut emitted = emission(r, &hit, lights_sampled);
+        if samples_lights {
+            emitted += direct_lighting(r, &hit, world, lights, background);
+        }
         if let Some((attenuation, scattered)) = hit.material.scatter(r, &hit) {
-            emitted + attenuation * color(&scattered, world, background, depth - 1)
+            let scattered = scattered.with_kind(RayKind::Indirect);
+            emitted
+                + attenuation
+                    * color(
+                        &scattered,
+                        world,
+                        lights,
+                        background,
+                        depth - 1,
+                        samples_lights,
+                    )
         } else {
             emitted
         }
     } else {
-        *background
+        escaped(r, background, lights_sampled)
     }
 }
 
-fn raytrace<T: Hittable>(
+fn spectral_color<T: Hittable>(
+    r: &Ray,
+    world: &Arc<T>,
+    lights: &LightList,
+    background: &dyn Background,
+    depth: i32,
+    lambdas: &mut Wavelengths,
+    lights_sampled: bool,
+) -> Spectrum {
+    if depth <= 0 {
+        return Spectrum::new(0.);
+    }
+    if let Some(mut hit) = world.hit(r, 0.001, f64::INFINITY) {
+        hit.footprint = hit.compute_footprint(r);
+        let samples_lights = hit.material.samples_lights();
+        let mut emitted = emission(r, &hit, lights_sampled);
+        if samples_lights {
+            emitted += direct_lighting(r, &hit, world, lights, background);
+        }
+        let emitted = Spectrum::from_rgb(emitted, lambdas);
+        if hit.material.is_dispersive() {
+            lambdas.terminate_secondary();
+        }
+        if let Some((attenuation, scattered)) = hit.material.scatter(r, &hit) {
+            let scattered = scattered
+                .with_wavelength(lambdas.hero())
+                .with_kind(RayKind::Indirect);
+            emitted
+                + Spectrum::from_rgb(attenuation, lambdas)
+                    * spectral_color(
+                        &scattered,
+                        world,
+                        lights,
+                        background,
+                        depth - 1,
+                        lambdas,
+                        samples_lights,
+                    )
+        } else {
+            emitted
+        }
+    } else {
+        Spectrum::from_rgb(escaped(r, background, lights_sampled), lambdas)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct RenderSettings {
     n_samples: i32,
-    nx: i32,
-    ny: i32,
     max_depth: i32,
+    // traces wavelengths instead of rgb triplets, needed for dispersion
+    spectral: bool,
+}
+
+fn raytrace<T: Hittable>(
+    settings: RenderSettings,
+    (nx, ny): (i32, i32),
     world: &Arc<T>,
-    background: &Color,
-    cam: &Camera,
+    lights: &LightList,
+    background: &dyn Background,
+    cam: &dyn CameraModel,
 ) -> RgbImage {
+    let RenderSettings {
+        n_samples,
+        max_depth,
+        spectral,
+    } = settings;
     let img_arc = Mutex::new(RgbImage::new(nx as u32, ny as u32));
+    // the distance to the neighbouring pixels, for ray differentials
+    let (du, dv) = (1. / (nx - 1) as f64, 1. / (ny - 1) as f64);
     (0..ny)
         .into_par_iter()
         .progress_count(ny as u64)
@@ -82,7 +245,26 @@ fn raytrace<T: Hittable>(
                 for _ in 0..n_samples {
                     let u = (i as f64 + utils::random_double()) / (nx - 1) as f64;
                     let v = (j as f64 + utils::random_double()) / (ny - 1) as f64;
-                    pixel_color += color(&cam.get_ray(u, v), world, background, max_depth);
+                    let r = match cam.generate_ray(u, v, du, dv) {
+                        Some(r) => r,
+                        None => continue,
+                    };
+                    pixel_color += if spectral {
+                        let mut lambdas = Wavelengths::sample_uniform(utils::random_double());
+                        let r = r.with_wavelength(lambdas.hero());
+                        spectral_color(
+                            &r,
+                            world,
+                            lights,
+                            background,
+                            max_depth,
+                            &mut lambdas,
+                            false,
+                        )
+                        .to_rgb(&lambdas)
+                    } else {
+                        color(&r, world, lights, background, max_depth, false)
+                    };
                 }
                 let mut img_buffer = img_arc.lock().unwrap();
                 img_buffer.put_pixel(i as u32, j as u32, Rgb(pixel_color.into_bytes(n_samples)));
@@ -95,15 +277,19 @@ fn raytrace<T: Hittable>(
 }
 
 fn main() {
-    let (cam, world, background) = scenes::two_perlin_spheres();
+    let (cam, world, lights, background) = scenes::two_perlin_spheres();
+    // the scene's camera, or another projection from the same place, e.g. Equirectangular::new(cam.view())
+    let cam: Box<dyn CameraModel> = Box::new(cam);
     let nx = 800;
-    let ny = (nx as f64 / cam.aspect_ratio) as i32;
+    let ny = (nx as f64 / cam.aspect_ratio()) as i32;
 
     const N_SAMPLES: i32 = 100;
     const MAX_DEPTH: i32 = 50;
 
+    // the output file, optionally followed by --spectral
     let args: Vec<String> = env::args().collect();
-    assert_eq!(args.len(), 2);
+    assert!(args.len() == 2 || (args.len() == 3 && args[2] == "--spectral"));
+    let spectral = args.len() == 3;
 
     println!("Scanlines processed:");
     assert!(&args[1].contains('.'));
@@ -120,7 +306,19 @@ fn main() {
 
     let start = Instant::now();
 
-    let img_buffer = raytrace(N_SAMPLES, nx, ny, MAX_DEPTH, &world, &background, &cam);
+    let settings = RenderSettings {
+        n_samples: N_SAMPLES,
+        max_depth: MAX_DEPTH,
+        spectral,
+    };
+    let img_buffer = raytrace(
+        settings,
+        (nx, ny),
+        &world,
+        &lights,
+        background.as_ref(),
+        cam.as_ref(),
+    );
 
     img_buffer
         .save_with_format(&args[1], format)
@@ -130,3 +328,52 @@ fn main() {
 
     println!("runtime: {:.2} hours", duration.as_secs_f32() / (60. * 60.));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn spectral_mode_renders_the_same_colors() {
+        let world = Arc::new(HittableList::new(vec![Arc::new(Sphere::new(
+            Vec3::new(0., 0., -3.),
+            1.,
+            Arc::new(Lambertian::new(Vec3::new(0.7, 0.3, 0.2))),
+        ))]));
+        let background = background::Constant::new(Vec3::new(0.4, 0.6, 0.9));
+        let cam = Camera::new(
+            1.,
+            40.,
+            Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            0.,
+            1.,
+            None,
+            None,
+        );
+        let render = |spectral: bool| {
+            let settings = RenderSettings {
+                n_samples: 400,
+                max_depth: 5,
+                spectral,
+            };
+            raytrace(
+                settings,
+                (4, 4),
+                &world,
+                &LightList::new(vec![]),
+                &background,
+                &cam,
+            )
+        };
+        // the round trip from rgb to a spectrum and back is close, not exact
+        let (rgb, spectral) = (render(false), render(true));
+        for (a, b) in rgb.pixels().zip(spectral.pixels()) {
+            for channel in 0..3 {
+                assert!((a[channel] as i32 - b[channel] as i32).abs() <= 20);
+            }
+        }
+    }
+}
@@ -1,10 +1,44 @@
-use super::{texture, utils, Arc, Color, HitRecord, Position, Ray, Texture, Vec3};
+use super::{
+    ies::IesProfile, onb::Onb, ray::RayDifferentials, texture, utils, Arc, Color, Direction,
+    HitRecord, Ray, Texture, Vec3,
+};
 
 pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
-    fn emitted(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
         Vec3::new(0., 0., 0.)
     }
+    /*
+        bsdf times the cosine term for light arriving from direction, used for explicitly sampled lights.
+        None for purely specular materials, which can't pick up light from a single direction
+    */
+    fn evaluate(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Direction) -> Option<Color> {
+        None
+    }
+    // whether evaluate is implemented, light reaching such materials along scattered rays was already sampled
+    fn samples_lights(&self) -> bool {
+        false
+    }
+    // whether scatter depends on the ray's wavelength, which ends the secondary wavelengths of a spectral path
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+}
+
+// specular bounces carry the ray differentials along, treating the surface as locally flat
+fn specular_differentials<F: Fn(&Vec3) -> Vec3>(
+    r_in: &Ray,
+    rec: &HitRecord,
+    bend: F,
+) -> Option<RayDifferentials> {
+    let differentials = r_in.differentials()?;
+    let (px, py) = rec.offset_points(r_in)?;
+    Some(RayDifferentials {
+        rx_origin: px,
+        rx_direction: bend(&differentials.rx_direction.normalize()),
+        ry_origin: py,
+        ry_direction: bend(&differentials.ry_direction.normalize()),
+    })
 }
 
 pub struct Lambertian {
@@ -31,10 +65,105 @@ impl Material for Lambertian {
         }
 
         Some((
-            self.albedo.value(rec.u, rec.v, &rec.p),
+            self.albedo.sample(&rec.texture_point()),
             Ray::new(rec.p, 0.5 * scatter_direction, Some(r_in.time())),
         ))
     }
+    fn evaluate(&self, _r_in: &Ray, rec: &HitRecord, direction: &Direction) -> Option<Color> {
+        let cos_theta = rec.normal.dot(*direction).max(0.);
+        Some(cos_theta / std::f64::consts::PI * self.albedo.sample(&rec.texture_point()))
+    }
+    fn samples_lights(&self) -> bool {
+        true
+    }
+}
+
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    sigma: Arc<dyn Texture>,
+}
+
+impl OrenNayar {
+    // sigma is the standard deviation of the microfacet slopes, in radians
+    pub fn new(col: Color, sigma: f64) -> Self {
+        OrenNayar {
+            albedo: Arc::new(texture::Solid::color_vec3(col)),
+            sigma: Arc::new(texture::Solid::color_vec3(Vec3::new_diagonal(sigma))),
+        }
+    }
+    pub fn textured(albedo: Arc<dyn Texture>, sigma: Arc<dyn Texture>) -> Self {
+        OrenNayar { albedo, sigma }
+    }
+    fn roughness_factor(sigma: f64, normal: &Vec3, wo: &Vec3, wi: &Vec3) -> f64 {
+        /*
+            qualitative model, relative to lambertian (the 1/pi and cosine terms
+            cancel out against the cosine-weighted sampling below)
+        */
+        let sigma2 = sigma * sigma;
+        let a = 1. - sigma2 / (2. * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let (cos_i, cos_o) = (wi.dot(*normal), wo.dot(*normal));
+        let (sin_i, sin_o) = (
+            (1. - cos_i * cos_i).max(0.).sqrt(),
+            (1. - cos_o * cos_o).max(0.).sqrt(),
+        );
+
+        let (tan_i, tan_o) = (*wi - cos_i * *normal, *wo - cos_o * *normal);
+        let cos_phi_diff = if tan_i.near_zero() || tan_o.near_zero() {
+            0.
+        } else {
+            tan_i.normalize().dot(tan_o.normalize()).max(0.)
+        };
+
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i.max(f64::EPSILON))
+        } else {
+            (sin_i, sin_o / cos_o.max(f64::EPSILON))
+        };
+
+        a + b * cos_phi_diff * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let sigma = self.sigma.sample(&rec.texture_point()).x();
+        let factor = OrenNayar::roughness_factor(
+            sigma,
+            &rec.normal,
+            &-r_in.direction().normalize(),
+            &scatter_direction.normalize(),
+        );
+
+        Some((
+            factor * self.albedo.sample(&rec.texture_point()),
+            Ray::new(rec.p, 0.5 * scatter_direction, Some(r_in.time())),
+        ))
+    }
+    fn evaluate(&self, r_in: &Ray, rec: &HitRecord, direction: &Direction) -> Option<Color> {
+        let cos_theta = rec.normal.dot(*direction);
+        if cos_theta <= 0. {
+            return Some(Color::new(0., 0., 0.));
+        }
+        let sigma = self.sigma.sample(&rec.texture_point()).x();
+        let factor = OrenNayar::roughness_factor(
+            sigma,
+            &rec.normal,
+            &-r_in.direction().normalize(),
+            direction,
+        );
+        Some(factor * cos_theta / std::f64::consts::PI * self.albedo.sample(&rec.texture_point()))
+    }
+    fn samples_lights(&self) -> bool {
+        true
+    }
 }
 
 pub struct Metal {
@@ -55,7 +184,10 @@ impl Material for Metal {
             rec.p,
             reflected + self.fuzziness * Vec3::random_in_unit_sphere(),
             Some(r_in.time()),
-        );
+        )
+        .with_differentials(specular_differentials(r_in, rec, |d| {
+            Vec3::reflect(d, &rec.normal)
+        }));
         if scattered.direction().dot(rec.normal) > 0. {
             Some((self.albedo, scattered))
         } else {
@@ -64,13 +196,70 @@ impl Material for Metal {
     }
 }
 
+pub enum RefractiveIndex {
+    Constant(f64),
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    // the index quoted for glasses is usually measured at the helium d-line
+    const D_LINE: f64 = 587.56;
+
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let lambda = wavelength.unwrap_or(RefractiveIndex::D_LINE) * 1e-3;
+        match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / (lambda * lambda),
+            RefractiveIndex::Sellmeier { b, c } => {
+                let lambda2 = lambda * lambda;
+                (1. + (0..3)
+                    .map(|i| b[i] * lambda2 / (lambda2 - c[i]))
+                    .sum::<f64>())
+                .sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+}
+
 pub struct Dielectric {
-    refr_index: f64,
+    refr_index: RefractiveIndex,
+    absorption: Color,
 }
 
 impl Dielectric {
     pub fn new(refr_index: f64) -> Self {
-        Dielectric { refr_index }
+        Dielectric {
+            refr_index: RefractiveIndex::Constant(refr_index),
+            absorption: Vec3::new_diagonal(0.),
+        }
+    }
+    // absorption coefficient per unit of distance travelled inside the object, per channel
+    pub fn absorbing(refr_index: f64, absorption: Color) -> Self {
+        Dielectric {
+            refr_index: RefractiveIndex::Constant(refr_index),
+            absorption,
+        }
+    }
+    // wavelength-dependent index, only distinguishable from a constant one in spectral mode
+    pub fn dispersive(refr_index: RefractiveIndex) -> Self {
+        Dielectric {
+            refr_index,
+            absorption: Vec3::new_diagonal(0.),
+        }
+    }
+    fn transmittance(&self, distance: f64) -> Color {
+        Vec3::new(
+            (-self.absorption.r() * distance).exp(),
+            (-self.absorption.g() * distance).exp(),
+            (-self.absorption.b() * distance).exp(),
+        )
     }
     fn reflectance(cosine: f64, refr_index: f64) -> f64 {
         let mut r0 = (1. - refr_index) / (1. + refr_index);
@@ -81,36 +270,149 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let refr_index = self.refr_index.at(r_in.wavelength());
         let refr_ratio = if rec.front_face {
-            1. / self.refr_index
+            1. / refr_index
         } else {
-            self.refr_index
+            refr_index
         };
         let unit_dir = r_in.direction().normalize();
         let cos_theta = 1.0_f64.min(rec.normal.dot(-unit_dir));
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
-        let dir = if (refr_ratio * sin_theta > 1.)
-            || Dielectric::reflectance(cos_theta, refr_ratio) > utils::random_double()
-        {
-            Vec3::reflect(&unit_dir, &rec.normal)
+        let reflects = (refr_ratio * sin_theta > 1.)
+            || Dielectric::reflectance(cos_theta, refr_ratio) > utils::random_double();
+        let bend = |d: &Vec3| {
+            if reflects {
+                Vec3::reflect(d, &rec.normal)
+            } else {
+                Vec3::refract(d, &rec.normal, refr_ratio)
+            }
+        };
+        let dir = bend(&unit_dir);
+
+        /*
+            a back face hit means the incoming ray travelled through the inside of the object,
+            so it gets attenuated according to beer-lambert
+        */
+        let attenuation = if rec.front_face {
+            Vec3::new(1., 1., 1.)
         } else {
-            Vec3::refract(&unit_dir, &rec.normal, refr_ratio)
+            self.transmittance(rec.t * r_in.direction().length())
         };
 
+        Some((
+            attenuation,
+            Ray::new(rec.p, dir, Some(r_in.time()))
+                .with_differentials(specular_differentials(r_in, rec, bend)),
+        ))
+    }
+    fn is_dispersive(&self) -> bool {
+        self.refr_index.is_dispersive()
+    }
+}
+
+pub struct ThinDielectric {
+    refr_index: f64,
+}
+
+impl ThinDielectric {
+    pub fn new(refr_index: f64) -> Self {
+        ThinDielectric { refr_index }
+    }
+}
+
+impl Material for ThinDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        /*
+            infinitely thin slab: the two parallel interfaces cancel out any bending,
+            and the reflectance accounts for the light bouncing back and forth between them
+        */
+        let unit_dir = r_in.direction().normalize();
+        let cos_theta = 1.0_f64.min(rec.normal.dot(-unit_dir));
+        let r = Dielectric::reflectance(cos_theta, 1. / self.refr_index);
+        let reflects = 2. * r / (1. + r) > utils::random_double();
+        let bend = |d: &Vec3| {
+            if reflects {
+                Vec3::reflect(d, &rec.normal)
+            } else {
+                *d
+            }
+        };
+        let dir = bend(&unit_dir);
+
         Some((
             Vec3::new(1., 1., 1.),
-            Ray::new(rec.p, dir, Some(r_in.time())),
+            Ray::new(rec.p, dir, Some(r_in.time()))
+                .with_differentials(specular_differentials(r_in, rec, bend)),
         ))
     }
 }
 
+pub enum Power {
+    Watts(f64),
+    Lumens(f64),
+}
+
+impl Power {
+    // peak luminous efficacy, at 555nm
+    pub const LUMENS_PER_WATT: f64 = 683.;
+
+    pub fn watts(&self) -> f64 {
+        match self {
+            Power::Watts(watts) => *watts,
+            Power::Lumens(lumens) => lumens / Power::LUMENS_PER_WATT,
+        }
+    }
+}
+
 pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
+    two_sided: bool,
+    // angular distribution, oriented with the nadir of the profile along w
+    profile: Option<(Arc<IesProfile>, Onb)>,
 }
 
 impl DiffuseLight {
     pub fn new(emit: Arc<dyn Texture>) -> Self {
-        DiffuseLight { emit }
+        DiffuseLight {
+            emit,
+            two_sided: true,
+            profile: None,
+        }
+    }
+    // only emits on the side the outward normal points to, see FlipFace
+    pub fn one_sided(emit: Arc<dyn Texture>) -> Self {
+        DiffuseLight {
+            emit,
+            two_sided: false,
+            profile: None,
+        }
+    }
+    /*
+        total power spread uniformly over a surface of the given area, the color only sets the hue.
+        a lambertian emitter has a radiance of power / (pi * area) per emitting side,
+        a black color or an empty surface emits nothing
+    */
+    pub fn from_power(color: Color, power: Power, area: f64, two_sided: bool) -> Self {
+        let sides = if two_sided { 2. } else { 1. };
+        let radiance = if color.luminance() > 0. && area > 0. {
+            power.watts() / (std::f64::consts::PI * area * sides * color.luminance()) * color
+        } else {
+            Color::new(0., 0., 0.)
+        };
+        DiffuseLight {
+            emit: Arc::new(texture::Solid::color_vec3(radiance)),
+            two_sided,
+            profile: None,
+        }
+    }
+    /*
+        scales the radiance by the profile relative to its brightest direction, aimed along
+        direction, so the texture gives the radiance seen looking into the peak of the beam
+    */
+    pub fn with_profile(mut self, profile: Arc<IesProfile>, direction: Direction) -> Self {
+        self.profile = Some((profile, Onb::from_w(direction)));
+        self
     }
 }
 
@@ -118,7 +420,183 @@ impl Material for DiffuseLight {
     fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
         None
     }
-    fn emitted(&self, u: f64, v: f64, p: &Position) -> Color {
-        self.emit.value(u, v, p)
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return Vec3::new(0., 0., 0.);
+        }
+        let scale = match &self.profile {
+            Some((profile, frame)) => profile.relative(&frame.to_local(-r_in.direction())),
+            None => 1.,
+        };
+        scale * self.emit.sample(&rec.texture_point())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rect::XYRect, Hittable};
+
+    #[test]
+    fn oren_nayar_reduces_to_lambertian() {
+        let albedo = Vec3::new(0.8, 0.5, 0.2);
+        let lambertian: Arc<dyn Material> = Arc::new(Lambertian::new(albedo));
+        let oren_nayar: Arc<dyn Material> = Arc::new(OrenNayar::new(albedo, 0.));
+
+        let r_in = Ray::new(Vec3::new(1., 1., 0.), Vec3::new(-1., -1., 0.2), None);
+        for material in [lambertian, oren_nayar].iter() {
+            let rec = HitRecord::new(
+                1.,
+                0.,
+                0.,
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                &r_in,
+                material,
+            );
+            for _ in 0..100 {
+                let (attenuation, scattered) = material.scatter(&r_in, &rec).unwrap();
+                assert!((attenuation - albedo).length() < 1e-12);
+                assert!(scattered.direction().dot(rec.normal) >= 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn absorbing_dielectric_darkens_with_thickness() {
+        let glass: Arc<dyn Material> =
+            Arc::new(Dielectric::absorbing(1.5, Vec3::new(0.1, 0.5, 1.)));
+        let r_in = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 1., 0.), None);
+        let exit = |t: f64| {
+            let rec = HitRecord::new(
+                t,
+                0.,
+                0.,
+                r_in.point(t),
+                Vec3::new(0., 1., 0.),
+                &r_in,
+                &glass,
+            );
+            glass.scatter(&r_in, &rec).unwrap().0
+        };
+
+        let (thin, thick) = (exit(1.), exit(2.));
+        assert!((thin.g() - (-0.5_f64).exp()).abs() < 1e-12);
+        for c in 0..3 {
+            assert!(thick[c] < thin[c]);
+        }
+    }
+
+    #[test]
+    fn oren_nayar_roughness_favors_backscatter() {
+        let normal = Vec3::new(0., 1., 0.);
+        let wo = Vec3::new(1., 1., 0.).normalize();
+        let wi = Vec3::new(1., 0.2, 0.).normalize();
+        assert_eq!(OrenNayar::roughness_factor(0., &normal, &wo, &wi), 1.);
+        assert!(OrenNayar::roughness_factor(0.5, &normal, &wo, &wi) > 1.);
+        let wi = Vec3::new(-1., 0.2, 0.).normalize();
+        assert!(OrenNayar::roughness_factor(0.5, &normal, &wo, &wi) < 1.);
+    }
+
+    #[test]
+    fn power_based_emission() {
+        let radiance = |light: DiffuseLight| light.emit.value(0., 0., &Vec3::new(0., 0., 0.));
+        let white = radiance(DiffuseLight::from_power(
+            Vec3::new_diagonal(1.),
+            Power::Watts(100.),
+            1.,
+            false,
+        ));
+        assert!((white.g() - 100. / std::f64::consts::PI).abs() < 1e-9);
+
+        // nothing to scale a black color or an empty surface by, so they emit nothing
+        let black = radiance(DiffuseLight::from_power(
+            Vec3::new(0., 0., 0.),
+            Power::Watts(100.),
+            1.,
+            true,
+        ));
+        let empty = radiance(DiffuseLight::from_power(
+            Vec3::new_diagonal(1.),
+            Power::Watts(100.),
+            0.,
+            false,
+        ));
+        assert_eq!((black.r(), black.g(), black.b()), (0., 0., 0.));
+        assert_eq!((empty.r(), empty.g(), empty.b()), (0., 0., 0.));
+    }
+
+    #[test]
+    fn dispersive_glass_bends_blue_more_than_red() {
+        // bk7, fitted both ways
+        let cauchy = RefractiveIndex::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        let sellmeier = RefractiveIndex::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        for n in [&cauchy, &sellmeier].iter() {
+            assert!(n.at(Some(400.)) > n.at(Some(700.)));
+            assert_eq!(n.at(None), n.at(Some(587.56)));
+            assert!((n.at(None) - 1.5168).abs() < 2e-3);
+        }
+
+        let glass = Dielectric::dispersive(sellmeier);
+        assert!(glass.is_dispersive());
+        assert!(!Dielectric::new(1.5).is_dispersive());
+
+        // the first transmitted direction at each wavelength, reflections are picked at random
+        let material: Arc<dyn Material> = Arc::new(glass);
+        let transmitted = |wavelength: f64| loop {
+            let r_in = Ray::new(Vec3::new(-1., 1., 0.), Vec3::new(1., -1., 0.), None)
+                .with_wavelength(wavelength);
+            let rec = HitRecord::new(
+                1.,
+                0.,
+                0.,
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                &r_in,
+                &material,
+            );
+            let (_, scattered) = material.scatter(&r_in, &rec).unwrap();
+            if scattered.direction().y() < 0. {
+                return scattered.direction();
+            }
+        };
+        // closer to the normal for the higher index
+        assert!(transmitted(400.).x() < transmitted(700.).x());
+    }
+
+    #[test]
+    fn thin_dielectric_passes_light_straight_through() {
+        let material: Arc<dyn Material> = Arc::new(ThinDielectric::new(1.5));
+        let pane = XYRect::new(-1., 1., -1., 1., 0., material.clone());
+        let scatter = |direction: Vec3| {
+            let r_in = Ray::new(Vec3::new(0.1, 0.2, 1.), direction, None);
+            let rec = pane.hit(&r_in, 0.001, f64::INFINITY).unwrap();
+            material.scatter(&r_in, &rec).unwrap().1.direction()
+        };
+
+        let oblique = Vec3::new(0.3, -0.2, -1.).normalize();
+        for _ in 0..100 {
+            let direction = scatter(oblique);
+            if direction.z() < 0. {
+                assert_eq!(
+                    (direction.x(), direction.y(), direction.z()),
+                    (oblique.x(), oblique.y(), oblique.z())
+                );
+            }
+        }
+
+        // at normal incidence both interfaces reflect r = ((n - 1) / (n + 1))^2 of the light
+        let r = (0.5_f64 / 2.5).powi(2);
+        let n = 20000;
+        let reflected = (0..n)
+            .filter(|_| scatter(Vec3::new(0., 0., -1.)).z() > 0.)
+            .count();
+        assert!((reflected as f64 / n as f64 - 2. * r / (1. + r)).abs() < 0.01);
     }
 }
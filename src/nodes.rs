@@ -0,0 +1,405 @@
+use super::{
+    procedural::ColorRamp,
+    texture::{Texture, TexturePoint},
+    utils, Arc, Color, Position, Vec3,
+};
+
+/*
+    Texture graph nodes. Every node is itself a texture built over Arc<dyn Texture> inputs,
+    so they can be nested freely, e.g. an image modulated by noise:
+        Multiply::new(image, Arc::new(Noise::new(4.)))
+    Nodes forward the whole TexturePoint to their inputs, so filtering and object space lookups
+    keep working through the graph.
+*/
+
+pub struct Add {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+}
+
+impl Add {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Add { a, b }
+    }
+}
+
+impl Texture for Add {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        self.a.sample(point) + self.b.sample(point)
+    }
+}
+
+pub struct Multiply {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+}
+
+impl Multiply {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Multiply { a, b }
+    }
+}
+
+impl Texture for Multiply {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        self.a.sample(point) * self.b.sample(point)
+    }
+}
+
+// constant blend, t = 0 gives a and t = 1 gives b
+pub struct Lerp {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+    t: f64,
+}
+
+impl Lerp {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>, t: f64) -> Self {
+        Lerp { a, b, t }
+    }
+}
+
+impl Texture for Lerp {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        (1. - self.t) * self.a.sample(point) + self.t * self.b.sample(point)
+    }
+}
+
+// blend driven by a mask texture, each channel of the mask blends its own channel
+pub struct Mix {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+    mask: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>, mask: Arc<dyn Texture>) -> Self {
+        Mix { a, b, mask }
+    }
+}
+
+impl Texture for Mix {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let mask = self.mask.sample(point);
+        (Vec3::new_diagonal(1.) - mask) * self.a.sample(point) + mask * self.b.sample(point)
+    }
+}
+
+// looks the luminance of the input up in a color ramp
+pub struct Ramp {
+    input: Arc<dyn Texture>,
+    ramp: ColorRamp,
+}
+
+impl Ramp {
+    pub fn new(input: Arc<dyn Texture>, ramp: ColorRamp) -> Self {
+        Ramp { input, ramp }
+    }
+}
+
+impl Texture for Ramp {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        self.ramp.value(self.input.sample(point).luminance())
+    }
+}
+
+/*
+    linearly maps [from.0, from.1] onto [to.0, to.1], per channel and without clamping.
+    an empty from range becomes a step at from.0
+*/
+pub struct Remap {
+    input: Arc<dyn Texture>,
+    from: (f64, f64),
+    to: (f64, f64),
+}
+
+impl Remap {
+    pub fn new(input: Arc<dyn Texture>, from: (f64, f64), to: (f64, f64)) -> Self {
+        Remap { input, from, to }
+    }
+}
+
+impl Texture for Remap {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let c = self.input.sample(point);
+        let width = self.from.1 - self.from.0;
+        let remap = |x: f64| {
+            if width == 0. {
+                if x < self.from.0 {
+                    self.to.0
+                } else {
+                    self.to.1
+                }
+            } else {
+                self.to.0 + (x - self.from.0) * (self.to.1 - self.to.0) / width
+            }
+        };
+        Vec3::new(remap(c.r()), remap(c.g()), remap(c.b()))
+    }
+}
+
+pub struct Clamp {
+    input: Arc<dyn Texture>,
+    min: f64,
+    max: f64,
+}
+
+impl Clamp {
+    pub fn new(input: Arc<dyn Texture>, min: f64, max: f64) -> Self {
+        Clamp { input, min, max }
+    }
+}
+
+impl Texture for Clamp {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let c = self.input.sample(point);
+        Vec3::new(
+            utils::clamp(c.r(), self.min, self.max),
+            utils::clamp(c.g(), self.min, self.max),
+            utils::clamp(c.b(), self.min, self.max),
+        )
+    }
+}
+
+pub struct Invert {
+    input: Arc<dyn Texture>,
+}
+
+impl Invert {
+    pub fn new(input: Arc<dyn Texture>) -> Self {
+        Invert { input }
+    }
+}
+
+impl Texture for Invert {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        Vec3::new_diagonal(1.) - self.input.sample(point)
+    }
+}
+
+// adjustments in HSV space: the hue is rotated by degrees, saturation and value are scaled
+pub struct HueSaturation {
+    input: Arc<dyn Texture>,
+    hue_shift: f64,
+    saturation: f64,
+    value: f64,
+}
+
+impl HueSaturation {
+    pub fn new(input: Arc<dyn Texture>, hue_shift: f64, saturation: f64, value: f64) -> Self {
+        HueSaturation {
+            input,
+            hue_shift,
+            saturation,
+            value,
+        }
+    }
+}
+
+impl Texture for HueSaturation {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let (h, s, v) = rgb_to_hsv(self.input.sample(point));
+        hsv_to_rgb(
+            (h + self.hue_shift).rem_euclid(360.),
+            utils::clamp(s * self.saturation, 0., 1.),
+            v * self.value,
+        )
+    }
+}
+
+// hue in degrees, saturation and value in [0, 1] for colors in [0, 1]
+fn rgb_to_hsv(c: Color) -> (f64, f64, f64) {
+    let (r, g, b) = (c.r(), c.g(), c.b());
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0. {
+        0.
+    } else if max == r {
+        60. * ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / delta + 2.)
+    } else {
+        60. * ((r - g) / delta + 4.)
+    };
+    let s = if max > 0. { delta / max } else { 0. };
+    (h, s, max)
+}
+
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> Color {
+    let c = v * s;
+    let x = c * (1. - ((h / 60.).rem_euclid(2.) - 1.).abs());
+    let (r, g, b) = match (h / 60.) as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = v - c;
+    Color::new(r + m, g + m, b + m)
+}
+
+#[derive(Copy, Clone)]
+pub enum CoordinateSpace {
+    World,
+    // the hit point before Translate/RotateY, so the pattern sticks to a moving object
+    Object,
+    // (u, v, 0), lets solid textures be laid out along the surface parametrization
+    UV,
+}
+
+// evaluates the input at p = scale * coordinates + offset in the chosen space
+pub struct Coordinates {
+    input: Arc<dyn Texture>,
+    space: CoordinateSpace,
+    scale: Vec3,
+    offset: Vec3,
+}
+
+impl Coordinates {
+    pub fn new(input: Arc<dyn Texture>, space: CoordinateSpace, scale: Vec3, offset: Vec3) -> Self {
+        Coordinates {
+            input,
+            space,
+            scale,
+            offset,
+        }
+    }
+}
+
+impl Texture for Coordinates {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let coordinates: Position = match self.space {
+            CoordinateSpace::World => point.p,
+            CoordinateSpace::Object => point.object_p,
+            CoordinateSpace::UV => Vec3::new(point.u, point.v, 0.),
+        };
+        let p = self.scale * coordinates + self.offset;
+        self.input.sample(&TexturePoint {
+            p,
+            object_p: p,
+            // the footprint was measured in the original space
+            footprint: None,
+            ..*point
+        })
+    }
+}
+
+// moves the input along velocity over time, e.g. flowing water or a conveyor belt
+pub struct Scroll {
+    input: Arc<dyn Texture>,
+    velocity: Vec3,
+}
+
+impl Scroll {
+    pub fn new(input: Arc<dyn Texture>, velocity: Vec3) -> Self {
+        Scroll { input, velocity }
+    }
+}
+
+impl Texture for Scroll {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let shift = point.time * self.velocity;
+        self.input.sample(&TexturePoint {
+            p: point.p - shift,
+            object_p: point.object_p - shift,
+            ..*point
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::Solid;
+
+    // the point it gets evaluated at, to see what a node passes on
+    struct Probe;
+
+    impl Texture for Probe {
+        fn sample(&self, point: &TexturePoint) -> Vec3 {
+            point.p
+        }
+    }
+
+    fn solid(r: f64, g: f64, b: f64) -> Arc<dyn Texture> {
+        Arc::new(Solid::color_vec3(Vec3::new(r, g, b)))
+    }
+
+    fn at(texture: &dyn Texture, p: Position) -> Vec3 {
+        texture.sample(&TexturePoint::new(0.25, 0.75, p))
+    }
+
+    #[test]
+    fn blends() {
+        let origin = Vec3::new(0., 0., 0.);
+        let (black, white) = (solid(0., 0., 0.), solid(1., 1., 1.));
+        assert_eq!(
+            at(&Lerp::new(black.clone(), white.clone(), 0.25), origin).g(),
+            0.25
+        );
+
+        // each channel of the mask picks between the inputs on its own
+        let mix = Mix::new(black, white, solid(0., 0.5, 1.));
+        let c = at(&mix, origin);
+        assert_eq!((c.r(), c.g(), c.b()), (0., 0.5, 1.));
+    }
+
+    #[test]
+    fn remap_and_clamp() {
+        let p = Vec3::new(-1., 0.5, 2.);
+        let c = at(&Remap::new(Arc::new(Probe), (0., 1.), (10., 20.)), p);
+        assert_eq!((c.r(), c.g(), c.b()), (0., 15., 30.));
+
+        // an empty range steps instead of dividing by zero
+        let c = at(&Remap::new(Arc::new(Probe), (0.5, 0.5), (10., 20.)), p);
+        assert_eq!((c.r(), c.g(), c.b()), (10., 20., 20.));
+
+        let c = at(&Clamp::new(Arc::new(Probe), 0., 1.), p);
+        assert_eq!((c.r(), c.g(), c.b()), (0., 0.5, 1.));
+    }
+
+    #[test]
+    fn coordinate_spaces() {
+        let point = TexturePoint {
+            object_p: Vec3::new(1., 2., 3.),
+            ..TexturePoint::new(0.25, 0.75, Vec3::new(4., 5., 6.))
+        };
+        let lookup = |space: CoordinateSpace| {
+            Coordinates::new(
+                Arc::new(Probe),
+                space,
+                Vec3::new_diagonal(2.),
+                Vec3::new(1., 0., 0.),
+            )
+            .sample(&point)
+        };
+        let world = lookup(CoordinateSpace::World);
+        assert_eq!((world.x(), world.y(), world.z()), (9., 10., 12.));
+        let object = lookup(CoordinateSpace::Object);
+        assert_eq!((object.x(), object.y(), object.z()), (3., 4., 6.));
+        let uv = lookup(CoordinateSpace::UV);
+        assert_eq!((uv.x(), uv.y(), uv.z()), (1.5, 1.5, 0.));
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for c in [
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(0.1, 0.7, 0.3),
+            Color::new(0.2, 0.3, 0.9),
+            Color::new(0.5, 0.5, 0.5),
+        ]
+        .iter()
+        {
+            let (h, s, v) = rgb_to_hsv(*c);
+            let back = hsv_to_rgb(h, s, v);
+            for i in 0..3 {
+                assert!((back[i] - c[i]).abs() < 1e-9, "{:?} -> {:?}", c, back);
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use super::{Direction, Vec3};
+
+// orthonormal basis around w, to go between world directions and a local frame where w is z
+#[derive(Copy, Clone)]
+pub struct Onb {
+    pub u: Direction,
+    pub v: Direction,
+    pub w: Direction,
+}
+
+impl Onb {
+    // u stays horizontal unless w is close to vertical
+    pub fn from_w(w: Direction) -> Self {
+        let w = w.normalize();
+        let a = if w.y().abs() > 0.9 {
+            Vec3::new(1., 0., 0.)
+        } else {
+            Vec3::new(0., 1., 0.)
+        };
+        let u = a.cross(w).normalize();
+        Onb {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    pub fn local(self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
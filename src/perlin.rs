@@ -1,75 +1,72 @@
 use super::{utils, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// seed used by the unseeded constructors, so renders come out the same between runs
+pub const DEFAULT_SEED: u64 = 0;
 
 pub struct Perlin {
     perm_x: Vec<usize>,
     perm_y: Vec<usize>,
     perm_z: Vec<usize>,
     ranvec: Vec<Vec3>,
+    // number of octaves summed by turbulence
+    octaves: u32,
 }
 
 impl Perlin {
     pub fn new() -> Self {
-        let point_count: usize = 256;
+        Perlin::seeded(DEFAULT_SEED)
+    }
+    pub fn seeded(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
         Perlin {
-            perm_x: Perlin::perlin_generate_perm(point_count),
-            perm_y: Perlin::perlin_generate_perm(point_count),
-            perm_z: Perlin::perlin_generate_perm(point_count),
-            ranvec: (0..point_count)
-                .map(|_| Vec3::random_unit_vector())
+            perm_x: utils::permutation(POINT_COUNT, &mut rng),
+            perm_y: utils::permutation(POINT_COUNT, &mut rng),
+            perm_z: utils::permutation(POINT_COUNT, &mut rng),
+            ranvec: (0..POINT_COUNT)
+                .map(|_| Perlin::random_unit_vector(&mut rng))
                 .collect(),
+            octaves: 7,
         }
     }
-    fn perlin_generate_perm(point_count: usize) -> Vec<usize> {
-        let mut p = vec![];
-        for i in 0..point_count {
-            p.push(i);
-        }
-
-        Perlin::permute(&mut p, point_count);
-        p
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
     }
-
-    fn permute(p: &mut Vec<usize>, n: usize) {
-        for i in (1..n).rev() {
-            let target = utils::random_int(0, i as i32) as usize;
-            p.swap(i, target);
-            //p.shuffle(&mut thread_rng());
-        }
+    pub fn octaves(&self) -> u32 {
+        self.octaves
     }
 
-    fn trilinear_interpolation(c: Vec<Vec<Vec<f64>>>, u: f64, v: f64, w: f64) -> f64 {
-        // hermite cube, but here we don't need the original values
-        let u = u * u * (3. - 2. * u);
-        let v = v * v * (3. - 2. * v);
-        let w = w * w * (3. - 2. * w);
-        let mut accumulator = 0.;
-        for i in 0..=1 {
-            for j in 0..=1 {
-                for k in 0..=1 {
-                    accumulator += (i as f64 * u + ((1 - i) as f64) * (1. - u))
-                        * (j as f64 * v + ((1 - j) as f64) * (1. - v))
-                        * (k as f64 * w + ((1 - k) as f64) * (1. - w))
-                        * c[i][j][k];
-                }
+    fn random_unit_vector(rng: &mut StdRng) -> Vec3 {
+        loop {
+            let v = Vec3::new(
+                rng.gen_range(-1.0..1.),
+                rng.gen_range(-1.0..1.),
+                rng.gen_range(-1.0..1.),
+            );
+            let length = v.length();
+            if length > 1e-3 && length <= 1. {
+                return v / length;
             }
         }
-
-        accumulator
     }
 
-    fn perlin_interpolation(c: Vec<Vec<Vec<Vec3>>>, u: f64, v: f64, w: f64) -> f64 {
+    fn perlin_interpolation(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
         let hermite_u = u * u * (3. - 2. * u);
         let hermite_v = v * v * (3. - 2. * v);
         let hermite_w = w * w * (3. - 2. * w);
         let mut accumulator = 0.;
-        for i in 0..=1 {
-            for j in 0..=1 {
-                for k in 0..=1 {
-                    let weight_v = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
-                    accumulator += (i as f64 * hermite_u + ((1 - i) as f64) * (1. - hermite_u))
-                        * (j as f64 * hermite_v + ((1 - j) as f64) * (1. - hermite_v))
-                        * (k as f64 * hermite_w + ((1 - k) as f64) * (1. - hermite_w))
-                        * c[i][j][k].dot(weight_v);
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let (i, j, k) = (i as f64, j as f64, k as f64);
+                    let weight_v = Vec3::new(u - i, v - j, w - k);
+                    accumulator += (i * hermite_u + (1. - i) * (1. - hermite_u))
+                        * (j * hermite_v + (1. - j) * (1. - hermite_v))
+                        * (k * hermite_w + (1. - k) * (1. - hermite_w))
+                        * gradient.dot(weight_v);
                 }
             }
         }
@@ -77,37 +74,54 @@ impl Perlin {
         accumulator
     }
 
-    pub fn generate_noise(&self, p: &Vec3) -> f64 {
+    // the lattice wraps around every period cells along each axis
+    fn lattice_noise(&self, p: &Vec3, period: [i64; 3]) -> f64 {
         let (u, v, w) = (
             p.x() - p.x().floor(),
             p.y() - p.y().floor(),
             p.z() - p.z().floor(),
         );
         let (i, j, k) = (
-            ((p.x().floor() as i32) & 255) as usize,
-            ((p.y().floor() as i32) & 255) as usize,
-            ((p.z().floor() as i32) & 255) as usize,
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
         );
-        let mut c = vec![vec![vec![Vec3::new_diagonal(0.); 2]; 2]; 2];
-
-        for ti in 0..=1 {
-            for tj in 0..=1 {
-                for tk in 0..=1 {
-                    c[ti][tj][tk] = self.ranvec[self.perm_x[(i + ti) & 255]
-                        ^ self.perm_y[(j + tj) & 255]
-                        ^ self.perm_z[(k + tk) & 255]];
+        let wrap = |x: i64, axis: usize| (x.rem_euclid(period[axis]) as usize) & (POINT_COUNT - 1);
+
+        let mut c = [[[Vec3::new_diagonal(0.); 2]; 2]; 2];
+        for (ti, plane) in c.iter_mut().enumerate() {
+            for (tj, row) in plane.iter_mut().enumerate() {
+                for (tk, gradient) in row.iter_mut().enumerate() {
+                    *gradient = self.ranvec[self.perm_x[wrap(i + ti as i64, 0)]
+                        ^ self.perm_y[wrap(j + tj as i64, 1)]
+                        ^ self.perm_z[wrap(k + tk as i64, 2)]];
                 }
             }
         }
 
-        Perlin::perlin_interpolation(c, u, v, w)
+        Perlin::perlin_interpolation(&c, u, v, w)
+    }
+
+    pub fn generate_noise(&self, p: &Vec3) -> f64 {
+        self.lattice_noise(p, [POINT_COUNT as i64; 3])
+    }
+
+    // tileable noise, repeats every period units along each axis
+    pub fn periodic_noise(&self, p: &Vec3, period: [u32; 3]) -> f64 {
+        self.lattice_noise(
+            p,
+            [
+                period[0].max(1) as i64,
+                period[1].max(1) as i64,
+                period[2].max(1) as i64,
+            ],
+        )
     }
 
     pub fn turbulence(&self, p: &Vec3) -> f64 {
-        const DEPTH: i32 = 7;
         let (mut accumulator, mut weight) = (0., 1.);
         let mut p_mut_copy = *p;
-        for _ in 0..DEPTH {
+        for _ in 0..self.octaves {
             accumulator += weight * self.generate_noise(&p_mut_copy);
             weight *= 0.5;
             p_mut_copy *= 2.;
@@ -115,4 +129,82 @@ impl Perlin {
 
         accumulator.abs()
     }
+
+    // turbulence that tiles like periodic_noise, each octave doubles the lattice period with the frequency
+    // the period saturates instead of overflowing for huge periods or many octaves
+    pub fn periodic_turbulence(&self, p: &Vec3, period: [u32; 3]) -> f64 {
+        let (mut accumulator, mut weight) = (0., 1.);
+        let mut p_mut_copy = *p;
+        let mut period = period;
+        for _ in 0..self.octaves {
+            accumulator += weight * self.periodic_noise(&p_mut_copy, period);
+            weight *= 0.5;
+            p_mut_copy *= 2.;
+            for axis in period.iter_mut() {
+                *axis = axis.saturating_mul(2);
+            }
+        }
+
+        accumulator.abs()
+    }
+
+    // fractional brownian motion, each octave scales the frequency by lacunarity and the amplitude by gain
+    pub fn fbm(&self, p: &Vec3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let (mut accumulator, mut weight) = (0., 1.);
+        let mut p_mut_copy = *p;
+        for _ in 0..octaves {
+            accumulator += weight * self.generate_noise(&p_mut_copy);
+            weight *= gain;
+            p_mut_copy *= lacunarity;
+        }
+
+        accumulator
+    }
+
+    // musgrave's ridged multifractal, sharp creases where the noise crosses zero
+    pub fn ridged(&self, p: &Vec3, octaves: u32, lacunarity: f64, gain: f64, offset: f64) -> f64 {
+        let (mut accumulator, mut weight, mut amplitude) = (0., 1., 1.);
+        let mut p_mut_copy = *p;
+        for _ in 0..octaves {
+            let mut signal = offset - self.generate_noise(&p_mut_copy).abs();
+            signal = signal * signal * weight;
+            accumulator += amplitude * signal;
+            weight = utils::clamp(signal * 2., 0., 1.);
+            amplitude *= gain;
+            p_mut_copy *= lacunarity;
+        }
+
+        accumulator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_and_periodic_noise() {
+        let p = Vec3::new(1.3, 2.7, -0.4);
+        assert_eq!(
+            Perlin::seeded(7).generate_noise(&p),
+            Perlin::seeded(7).generate_noise(&p)
+        );
+
+        let noise = Perlin::new().with_octaves(3);
+        let period = [3, 5, 2];
+        let shifted = p + Vec3::new(3., 10., -4.);
+        assert!(
+            (noise.periodic_noise(&p, period) - noise.periodic_noise(&shifted, period)).abs()
+                < 1e-12
+        );
+        assert!(
+            (noise.periodic_turbulence(&p, period) - noise.periodic_turbulence(&shifted, period))
+                .abs()
+                < 1e-12
+        );
+
+        // doubling u32::MAX / 2 a few times used to overflow
+        let huge = Perlin::new().with_octaves(40);
+        assert!(huge.periodic_turbulence(&p, [u32::MAX / 2; 3]).is_finite());
+    }
 }
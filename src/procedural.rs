@@ -0,0 +1,436 @@
+use super::{
+    simplex::Simplex, texture::TexturePoint, utils, Color, Direction, Perlin, Position, Texture,
+    Vec3, Worley,
+};
+
+/*
+    Configurable procedural textures. Most of them compute a scalar and look it up in a color ramp.
+    The perlin based ones can be animated, cellular patterns and gradients stay still.
+*/
+
+/*
+    perlin noise for still patterns. the animated() builders switch to 4d simplex noise,
+    moving along w at speed noise cells per unit of time
+*/
+struct NoiseSource {
+    perlin: Perlin,
+    evolution: Option<(Simplex, f64)>,
+}
+
+impl NoiseSource {
+    fn new() -> Self {
+        NoiseSource {
+            perlin: Perlin::new(),
+            evolution: None,
+        }
+    }
+    fn animated(speed: f64) -> Self {
+        NoiseSource {
+            evolution: Some((Simplex::new(), speed)),
+            ..NoiseSource::new()
+        }
+    }
+    fn turbulence(&self, p: &Vec3, time: f64) -> f64 {
+        match &self.evolution {
+            Some((simplex, speed)) => simplex.turbulence4(p, speed * time, self.perlin.octaves()),
+            None => self.perlin.turbulence(p),
+        }
+    }
+    fn fbm(&self, p: &Vec3, time: f64, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        match &self.evolution {
+            Some((simplex, speed)) => simplex.fbm4(p, speed * time, octaves, lacunarity, gain),
+            None => self.perlin.fbm(p, octaves, lacunarity, gain),
+        }
+    }
+    fn ridged(
+        &self,
+        p: &Vec3,
+        time: f64,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+        offset: f64,
+    ) -> f64 {
+        match &self.evolution {
+            Some((simplex, speed)) => {
+                simplex.ridged4(p, speed * time, octaves, lacunarity, gain, offset)
+            }
+            None => self.perlin.ridged(p, octaves, lacunarity, gain, offset),
+        }
+    }
+}
+
+pub struct ColorRamp {
+    stops: Vec<(f64, Color)>,
+}
+
+impl ColorRamp {
+    // stops are (position, color) pairs, values in between get linearly interpolated
+    pub fn new(mut stops: Vec<(f64, Color)>) -> Self {
+        assert!(!stops.is_empty());
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        ColorRamp { stops }
+    }
+    pub fn two_colors(c0: Color, c1: Color) -> Self {
+        ColorRamp::new(vec![(0., c0), (1., c1)])
+    }
+    pub fn grayscale() -> Self {
+        ColorRamp::two_colors(Vec3::new_diagonal(0.), Vec3::new_diagonal(1.))
+    }
+    pub fn value(&self, t: f64) -> Color {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for window in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (window[0], window[1]);
+            if t <= t1 {
+                let s = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1. };
+                return (1. - s) * c0 + s * c1;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum CellularFeature {
+    F1,
+    F2,
+    // distance to the cell borders, gives a cracked or tiled look
+    F2MinusF1,
+}
+
+pub struct Cellular {
+    noise: Worley,
+    scale: f64,
+    feature: CellularFeature,
+    ramp: ColorRamp,
+}
+
+impl Cellular {
+    pub fn new(scale: f64, feature: CellularFeature, ramp: ColorRamp) -> Self {
+        Cellular {
+            noise: Worley::new(),
+            scale,
+            feature,
+            ramp,
+        }
+    }
+}
+
+impl Texture for Cellular {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let (f1, f2) = self.noise.distances(&(self.scale * point.p));
+        self.ramp.value(match self.feature {
+            CellularFeature::F1 => f1,
+            CellularFeature::F2 => f2,
+            CellularFeature::F2MinusF1 => f2 - f1,
+        })
+    }
+}
+
+pub struct Fbm {
+    noise: NoiseSource,
+    scale: f64,
+    octaves: u32,
+    lacunarity: f64,
+    gain: f64,
+    ramp: ColorRamp,
+}
+
+impl Fbm {
+    pub fn new(scale: f64, octaves: u32, lacunarity: f64, gain: f64, ramp: ColorRamp) -> Self {
+        Fbm {
+            noise: NoiseSource::new(),
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+            ramp,
+        }
+    }
+    pub fn animated(mut self, speed: f64) -> Self {
+        self.noise = NoiseSource::animated(speed);
+        self
+    }
+}
+
+impl Texture for Fbm {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let n = self.noise.fbm(
+            &(self.scale * point.p),
+            point.time,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+        );
+        self.ramp.value(0.5 * (1. + n))
+    }
+}
+
+// perlin turbulence as a grayscale value, meant to be fed into a ramp node
+pub struct Turbulence {
+    noise: NoiseSource,
+    scale: f64,
+}
+
+impl Turbulence {
+    pub fn new(scale: f64) -> Self {
+        Turbulence {
+            noise: NoiseSource::new(),
+            scale,
+        }
+    }
+    pub fn animated(mut self, speed: f64) -> Self {
+        self.noise = NoiseSource::animated(speed);
+        self
+    }
+}
+
+impl Texture for Turbulence {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        Vec3::new_diagonal(self.noise.turbulence(&(self.scale * point.p), point.time))
+    }
+}
+
+pub struct RidgedMultifractal {
+    noise: NoiseSource,
+    scale: f64,
+    octaves: u32,
+    lacunarity: f64,
+    gain: f64,
+    offset: f64,
+    ramp: ColorRamp,
+}
+
+impl RidgedMultifractal {
+    pub fn new(
+        scale: f64,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+        offset: f64,
+        ramp: ColorRamp,
+    ) -> Self {
+        RidgedMultifractal {
+            noise: NoiseSource::new(),
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+            offset,
+            ramp,
+        }
+    }
+    pub fn animated(mut self, speed: f64) -> Self {
+        self.noise = NoiseSource::animated(speed);
+        self
+    }
+}
+
+impl Texture for RidgedMultifractal {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let n = self.noise.ridged(
+            &(self.scale * point.p),
+            point.time,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+            self.offset,
+        );
+        // the sum of the octaves' amplitudes, so that the ramp gets used over [0, 1]
+        let max: f64 = (0..self.octaves)
+            .map(|i| self.gain.powi(i as i32) * self.offset * self.offset)
+            .sum();
+        self.ramp.value(n / max)
+    }
+}
+
+pub struct Wood {
+    noise: NoiseSource,
+    scale: f64,
+    rings: f64,
+    distortion: f64,
+    ramp: ColorRamp,
+}
+
+impl Wood {
+    // concentric rings around the y axis, rings per unit of distance, distorted by turbulence
+    pub fn new(scale: f64, rings: f64, distortion: f64, ramp: ColorRamp) -> Self {
+        Wood {
+            noise: NoiseSource::new(),
+            scale,
+            rings,
+            distortion,
+            ramp,
+        }
+    }
+    pub fn animated(mut self, speed: f64) -> Self {
+        self.noise = NoiseSource::animated(speed);
+        self
+    }
+}
+
+impl Texture for Wood {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let p = self.scale * point.p;
+        let radius = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let r = self.rings * radius + self.distortion * self.noise.turbulence(&p, point.time);
+        // latewood is narrower than earlywood
+        self.ramp.value((r - r.floor()).powi(3))
+    }
+}
+
+pub struct Marble {
+    noise: NoiseSource,
+    scale: f64,
+    axis: Direction,
+    turbulence: f64,
+    ramp: ColorRamp,
+}
+
+impl Marble {
+    // veins run perpendicular to axis, turbulence sets how much they meander
+    pub fn new(scale: f64, axis: Direction, turbulence: f64, ramp: ColorRamp) -> Self {
+        Marble {
+            noise: NoiseSource::new(),
+            scale,
+            axis: axis.normalize(),
+            turbulence,
+            ramp,
+        }
+    }
+    pub fn animated(mut self, speed: f64) -> Self {
+        self.noise = NoiseSource::animated(speed);
+        self
+    }
+}
+
+impl Texture for Marble {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        self.ramp.value(
+            0.5 * (1.
+                + (self.scale * point.p.dot(self.axis)
+                    + self.turbulence * self.noise.turbulence(&point.p, point.time))
+                .sin()),
+        )
+    }
+}
+
+pub struct LinearGradient {
+    start: Position,
+    end: Position,
+    ramp: ColorRamp,
+}
+
+impl LinearGradient {
+    // ramp goes from 0 at start to 1 at end, constant on planes perpendicular to end - start
+    pub fn new(start: Position, end: Position, ramp: ColorRamp) -> Self {
+        LinearGradient { start, end, ramp }
+    }
+}
+
+impl Texture for LinearGradient {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let axis = self.end - self.start;
+        self.ramp.value(utils::clamp(
+            (point.p - self.start).dot(axis) / axis.squared_length(),
+            0.,
+            1.,
+        ))
+    }
+}
+
+pub struct RadialGradient {
+    center: Position,
+    radius: f64,
+    ramp: ColorRamp,
+}
+
+impl RadialGradient {
+    pub fn new(center: Position, radius: f64, ramp: ColorRamp) -> Self {
+        RadialGradient {
+            center,
+            radius,
+            ramp,
+        }
+    }
+}
+
+impl Texture for RadialGradient {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        self.ramp.value(utils::clamp(
+            (point.p - self.center).length() / self.radius,
+            0.,
+            1.,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_ramp_interpolates_between_stops() {
+        let ramp = ColorRamp::new(vec![
+            (1., Vec3::new(0., 0., 1.)),
+            (0., Vec3::new(1., 0., 0.)),
+            (0.5, Vec3::new(0., 1., 0.)),
+        ]);
+        assert_eq!(ramp.value(-1.).r(), 1.);
+        assert_eq!(ramp.value(0.25).r(), 0.5);
+        assert_eq!(ramp.value(0.25).g(), 0.5);
+        assert_eq!(ramp.value(0.5).g(), 1.);
+        assert_eq!(ramp.value(2.).b(), 1.);
+
+        // a nan stop sorts past the others instead of panicking
+        let ramp = ColorRamp::new(vec![
+            (f64::NAN, Vec3::new(0., 0., 1.)),
+            (0., Vec3::new(1., 0., 0.)),
+            (1., Vec3::new(0., 1., 0.)),
+        ]);
+        assert_eq!(ramp.value(0.5).r(), 0.5);
+        assert_eq!(ramp.value(0.5).g(), 0.5);
+    }
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        let noise = Worley::new();
+        for _ in 0..100 {
+            let (f1, f2) = noise.distances(&Vec3::random_from_range(-10., 10.));
+            assert!(f1 <= f2);
+            assert!(f1 < 3_f64.sqrt() * 2.);
+        }
+    }
+
+    #[test]
+    fn animated_patterns_evolve_over_time() {
+        let ramp = ColorRamp::grayscale;
+        let textures: Vec<(Box<dyn Texture>, Box<dyn Texture>)> = vec![
+            (
+                Box::new(Fbm::new(2., 5, 2., 0.5, ramp())),
+                Box::new(Fbm::new(2., 5, 2., 0.5, ramp()).animated(1.)),
+            ),
+            (
+                Box::new(Marble::new(2., Vec3::new(1., 0., 0.), 5., ramp())),
+                Box::new(Marble::new(2., Vec3::new(1., 0., 0.), 5., ramp()).animated(1.)),
+            ),
+            (
+                Box::new(RidgedMultifractal::new(2., 5, 2., 0.5, 1., ramp())),
+                Box::new(RidgedMultifractal::new(2., 5, 2., 0.5, 1., ramp()).animated(1.)),
+            ),
+        ];
+        let p = Vec3::new(0.3, 0.7, 1.1);
+        for (still, animated) in textures.iter() {
+            let at = |texture: &dyn Texture, time: f64| {
+                texture
+                    .sample(&TexturePoint::new(0., 0., p).with_time(time))
+                    .r()
+            };
+            assert_eq!(at(still.as_ref(), 0.), at(still.as_ref(), 0.7));
+            assert_ne!(at(animated.as_ref(), 0.), at(animated.as_ref(), 0.7));
+        }
+    }
+}
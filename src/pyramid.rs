@@ -1,4 +1,6 @@
-use super::{Arc, HitRecord, Hittable, HittableList, Ray, Triangle, Vec3, XZRect, AABB};
+use super::{
+    Arc, Direction, HitRecord, Hittable, HittableList, Position, Ray, Triangle, Vec3, XZRect, AABB,
+};
 
 pub struct Pyramid {
     faces: HittableList,
@@ -62,4 +64,16 @@ impl Hittable for Pyramid {
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
         self.faces.bounding_box(t0, t1)
     }
+    fn area(&self) -> f64 {
+        self.faces.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.faces.sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.faces.sample_direction(origin)
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.faces.direction_pdf(origin, direction)
+    }
 }
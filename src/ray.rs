@@ -1,9 +1,33 @@
 use super::{Direction, Position, Vec3};
 
+// rays offset by one pixel in x and y, used to estimate texture footprints
+#[derive(Copy, Clone)]
+pub struct RayDifferentials {
+    pub rx_origin: Position,
+    pub rx_direction: Direction,
+    pub ry_origin: Position,
+    pub ry_direction: Direction,
+}
+
+// what the integrator traces a ray for, objects can be hidden from some kinds of rays
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RayKind {
+    Camera,
+    // reflected, refracted or scattered off a surface or a medium
+    Indirect,
+    // testing whether a light is visible
+    Shadow,
+    // looking up the surface of an emitter while sampling it as a light, never hidden
+    Light,
+}
+
 pub struct Ray {
     a: Position,
     b: Position,
     time: f64,
+    wavelength: Option<f64>,
+    differentials: Option<RayDifferentials>,
+    kind: RayKind,
 }
 
 impl Ray {
@@ -12,9 +36,28 @@ impl Ray {
             a,
             b,
             time: time.unwrap_or(0.),
+            wavelength: None,
+            differentials: None,
+            kind: RayKind::Camera,
         }
     }
 
+    // tags the ray with the hero wavelength (in nm) of the path in spectral mode
+    pub fn with_wavelength(mut self, wavelength: f64) -> Self {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
+    pub fn with_differentials(mut self, differentials: Option<RayDifferentials>) -> Self {
+        self.differentials = differentials;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: RayKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn origin(&self) -> Position {
         self.a
     }
@@ -30,4 +73,16 @@ impl Ray {
     pub fn time(&self) -> f64 {
         self.time
     }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn differentials(&self) -> Option<RayDifferentials> {
+        self.differentials
+    }
+
+    pub fn kind(&self) -> RayKind {
+        self.kind
+    }
 }
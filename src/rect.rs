@@ -1,4 +1,145 @@
-use super::{Arc, HitRecord, Hittable, HittableList, Material, Ray, Vec3, AABB};
+use super::{
+    hittable, ray::RayKind, utils, Arc, Direction, HitRecord, Hittable, HittableList, Material,
+    Position, Ray, Vec3, AABB,
+};
+use std::f64::consts::PI;
+
+/*
+    Rectangle as seen from a point, for sampling it uniformly over solid angle, after
+    Ureña, Fajardo & King - An Area-Preserving Parametrization for Spherical Rectangles.
+    The rectangle is corner + s * ex + t * ey for s, t in [0, 1] with ex and ey perpendicular,
+    everything is worked out in a frame with the origin at the shaded point and z along the normal.
+*/
+struct SphericalRectangle {
+    origin: Position,
+    x: Direction,
+    y: Direction,
+    z: Direction,
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+    solid_angle: f64,
+}
+
+impl SphericalRectangle {
+    fn new(origin: Position, corner: Position, ex: Direction, ey: Direction) -> Option<Self> {
+        let (width, height) = (ex.length(), ey.length());
+        let (x, y) = (ex / width, ey / height);
+        let mut z = x.cross(y);
+        let d = corner - origin;
+        let mut z0 = d.dot(z);
+        // seen edge on, the rectangle covers no solid angle
+        if z0.abs() < 1e-9 {
+            return None;
+        }
+        if z0 > 0. {
+            z = -z;
+            z0 = -z0;
+        }
+        let (x0, y0) = (d.dot(x), d.dot(y));
+        let (x1, y1) = (x0 + width, y0 + height);
+
+        // normals of the planes through the origin and each edge
+        let n0 = Vec3::new(0., z0, -y0).normalize();
+        let n1 = Vec3::new(-z0, 0., x1).normalize();
+        let n2 = Vec3::new(0., -z0, y1).normalize();
+        let n3 = Vec3::new(z0, 0., -x0).normalize();
+        let angle = |a: Vec3, b: Vec3| utils::clamp(-a.dot(b), -1., 1.).acos();
+        let (g0, g1, g2, g3) = (angle(n0, n1), angle(n1, n2), angle(n2, n3), angle(n3, n0));
+        let k = 2. * PI - g2 - g3;
+
+        Some(SphericalRectangle {
+            origin,
+            x,
+            y,
+            z,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            b0: n0.z(),
+            b1: n2.z(),
+            k,
+            solid_angle: g0 + g1 - k,
+        })
+    }
+
+    // unit direction towards the rectangle for u, v in [0, 1)
+    fn sample(&self, u: f64, v: f64) -> Direction {
+        let au = u * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = utils::clamp(
+            (1. / (fu * fu + self.b0 * self.b0).sqrt()).copysign(fu),
+            -1.,
+            1.,
+        );
+        let xu = utils::clamp(
+            -(cu * self.z0) / (1. - cu * cu).max(1e-12).sqrt(),
+            self.x0,
+            self.x1,
+        );
+
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + v * (h1 - h0);
+        let yv = if hv * hv < 1. - 1e-9 {
+            hv * d / (1. - hv * hv).sqrt()
+        } else {
+            self.y1
+        };
+
+        let point = self.origin + xu * self.x + yv * self.y + self.z0 * self.z;
+        (point - self.origin).normalize()
+    }
+}
+
+// directions towards a rectangle, area sampled when it's too small or far away for the solid angle to be accurate
+fn rectangle_direction(
+    origin: &Position,
+    corner: Position,
+    ex: Direction,
+    ey: Direction,
+) -> Option<(Direction, f64)> {
+    match SphericalRectangle::new(*origin, corner, ex, ey) {
+        Some(rectangle) if rectangle.solid_angle > 1e-6 => Some((
+            rectangle.sample(utils::random_double(), utils::random_double()),
+            1. / rectangle.solid_angle,
+        )),
+        Some(_) => {
+            let point = corner + utils::random_double() * ex + utils::random_double() * ey;
+            let normal = ex.cross(ey).normalize();
+            hittable::area_to_solid_angle(origin, &point, &normal, ex.cross(ey).length())
+        }
+        None => None,
+    }
+}
+
+fn rectangle_pdf(
+    object: &dyn Hittable,
+    origin: &Position,
+    direction: &Direction,
+    corner: Position,
+    ex: Direction,
+    ey: Direction,
+) -> f64 {
+    let ray = Ray::new(*origin, *direction, None).with_kind(RayKind::Light);
+    let hit = match object.hit(&ray, 0.001, f64::INFINITY) {
+        Some(hit) => hit,
+        None => return 0.,
+    };
+    match SphericalRectangle::new(*origin, corner, ex, ey) {
+        Some(rectangle) if rectangle.solid_angle > 1e-6 => 1. / rectangle.solid_angle,
+        Some(_) => hittable::hit_to_solid_angle(&hit, direction, ex.cross(ey).length()),
+        None => 0.,
+    }
+}
 
 pub struct XYRect {
     x0: f64,
@@ -20,6 +161,15 @@ impl XYRect {
             material,
         }
     }
+    fn corner(&self) -> Position {
+        Vec3::new(self.x0, self.y0, self.k)
+    }
+    fn edges(&self) -> (Direction, Direction) {
+        (
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., self.y1 - self.y0, 0.),
+        )
+    }
 }
 
 impl Hittable for XYRect {
@@ -37,15 +187,21 @@ impl Hittable for XYRect {
                     (x - self.x0) / (self.x1 - self.x0),
                     (y - self.y0) / (self.y1 - self.y0),
                 );
-                Some(HitRecord::new(
-                    t,
-                    u,
-                    v,
-                    r.point(t),
-                    Vec3::new(0., 0., 1.),
-                    r,
-                    &self.material,
-                ))
+                Some(
+                    HitRecord::new(
+                        t,
+                        u,
+                        v,
+                        r.point(t),
+                        Vec3::new(0., 0., 1.),
+                        r,
+                        &self.material,
+                    )
+                    .with_partials(
+                        Vec3::new(self.x1 - self.x0, 0., 0.),
+                        Vec3::new(0., self.y1 - self.y0, 0.),
+                    ),
+                )
             }
         }
     }
@@ -55,6 +211,29 @@ impl Hittable for XYRect {
             Vec3::new(self.x1, self.y1, self.k + 0.0001),
         ))
     }
+
+    fn area(&self) -> f64 {
+        let (ex, ey) = self.edges();
+        ex.cross(ey).length()
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let (ex, ey) = self.edges();
+        Some((
+            self.corner() + utils::random_double() * ex + utils::random_double() * ey,
+            Vec3::new(0., 0., 1.),
+        ))
+    }
+
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (ex, ey) = self.edges();
+        rectangle_direction(origin, self.corner(), ex, ey)
+    }
+
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let (ex, ey) = self.edges();
+        rectangle_pdf(self, origin, direction, self.corner(), ex, ey)
+    }
 }
 
 pub struct YZRect {
@@ -77,6 +256,15 @@ impl YZRect {
             material,
         }
     }
+    fn corner(&self) -> Position {
+        Vec3::new(self.k, self.y0, self.z0)
+    }
+    fn edges(&self) -> (Direction, Direction) {
+        (
+            Vec3::new(0., self.y1 - self.y0, 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+        )
+    }
 }
 
 impl Hittable for YZRect {
@@ -94,15 +282,21 @@ impl Hittable for YZRect {
                     (y - self.y0) / (self.y1 - self.y0),
                     (z - self.z0) / (self.z1 - self.z0),
                 );
-                Some(HitRecord::new(
-                    t,
-                    u,
-                    v,
-                    r.point(t),
-                    Vec3::new(1., 0., 0.),
-                    r,
-                    &self.material,
-                ))
+                Some(
+                    HitRecord::new(
+                        t,
+                        u,
+                        v,
+                        r.point(t),
+                        Vec3::new(1., 0., 0.),
+                        r,
+                        &self.material,
+                    )
+                    .with_partials(
+                        Vec3::new(0., self.y1 - self.y0, 0.),
+                        Vec3::new(0., 0., self.z1 - self.z0),
+                    ),
+                )
             }
         }
     }
@@ -112,6 +306,29 @@ impl Hittable for YZRect {
             Vec3::new(self.k + 0.0001, self.y1, self.z1),
         ))
     }
+
+    fn area(&self) -> f64 {
+        let (ex, ey) = self.edges();
+        ex.cross(ey).length()
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let (ex, ey) = self.edges();
+        Some((
+            self.corner() + utils::random_double() * ex + utils::random_double() * ey,
+            Vec3::new(1., 0., 0.),
+        ))
+    }
+
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (ex, ey) = self.edges();
+        rectangle_direction(origin, self.corner(), ex, ey)
+    }
+
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let (ex, ey) = self.edges();
+        rectangle_pdf(self, origin, direction, self.corner(), ex, ey)
+    }
 }
 
 pub struct XZRect {
@@ -134,6 +351,15 @@ impl XZRect {
             material,
         }
     }
+    fn corner(&self) -> Position {
+        Vec3::new(self.x0, self.k, self.z0)
+    }
+    fn edges(&self) -> (Direction, Direction) {
+        (
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+        )
+    }
 }
 
 impl Hittable for XZRect {
@@ -151,15 +377,21 @@ impl Hittable for XZRect {
                     (x - self.x0) / (self.x1 - self.x0),
                     (z - self.z0) / (self.z1 - self.z0),
                 );
-                Some(HitRecord::new(
-                    t,
-                    u,
-                    v,
-                    r.point(t),
-                    Vec3::new(0., 1., 0.),
-                    r,
-                    &self.material,
-                ))
+                Some(
+                    HitRecord::new(
+                        t,
+                        u,
+                        v,
+                        r.point(t),
+                        Vec3::new(0., 1., 0.),
+                        r,
+                        &self.material,
+                    )
+                    .with_partials(
+                        Vec3::new(self.x1 - self.x0, 0., 0.),
+                        Vec3::new(0., 0., self.z1 - self.z0),
+                    ),
+                )
             }
         }
     }
@@ -169,6 +401,29 @@ impl Hittable for XZRect {
             Vec3::new(self.x1, self.k - 0.0001, self.z1),
         ))
     }
+
+    fn area(&self) -> f64 {
+        let (ex, ey) = self.edges();
+        ex.cross(ey).length()
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let (ex, ey) = self.edges();
+        Some((
+            self.corner() + utils::random_double() * ex + utils::random_double() * ey,
+            Vec3::new(0., 1., 0.),
+        ))
+    }
+
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let (ex, ey) = self.edges();
+        rectangle_direction(origin, self.corner(), ex, ey)
+    }
+
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let (ex, ey) = self.edges();
+        rectangle_pdf(self, origin, direction, self.corner(), ex, ey)
+    }
 }
 
 pub struct Cuboid {
@@ -239,4 +494,16 @@ impl Hittable for Cuboid {
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
         self.sides.bounding_box(t0, t1)
     }
+    fn area(&self) -> f64 {
+        self.sides.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.sides.sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.sides.sample_direction(origin)
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.sides.direction_pdf(origin, direction)
+    }
 }
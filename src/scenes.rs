@@ -1,13 +1,19 @@
 use material::{Dielectric, Lambertian, Material};
 
+use crate::environment::EnvironmentMap;
+use crate::ies::IesProfile;
+use crate::sky::PreethamSky;
 use crate::volume::ConstantMedium;
 
 use super::{
-    material, texture, utils, Arc, BVHNode, Camera, Cuboid, HittableList, MovingSphere, Plane,
-    Pyramid, RotateY, Sphere, Translate, Triangle, Vec3, XYRect, XZRect, YZRect,
+    background::{self, Background},
+    light, material, spectrum, texture, utils, Arc, BVHNode, Camera, Cuboid, FlipFace,
+    HittableList, LightList, MovingSphere, Plane, Pyramid, RotateY, Sphere, Translate, Triangle,
+    Vec3, XYRect, XZRect, YZRect,
 };
+use std::f64::consts::PI;
 
-pub fn random_scene() -> (Camera, Arc<HittableList>, Vec3) {
+pub fn random_scene() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
     let mut world: HittableList = HittableList::new(vec![]);
     let checker = Arc::new(texture::Checker::from_vec3(
         Vec3::new(0.2, 0.3, 0.1),
@@ -94,10 +100,15 @@ pub fn random_scene() -> (Camera, Arc<HittableList>, Vec3) {
         None,
     );
 
-    (cam, Arc::new(world), Vec3::new(0.7, 0.8, 1.))
+    (
+        cam,
+        Arc::new(world),
+        LightList::new(vec![]),
+        Arc::new(background::VerticalGradient::sky()),
+    )
 }
 
-pub fn two_spheres() -> (Camera, Arc<HittableList>, Vec3) {
+pub fn two_spheres() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
     let mut objects = HittableList::new(vec![]);
 
     let checker = Arc::new(texture::Checker::from_vec3(
@@ -135,10 +146,15 @@ pub fn two_spheres() -> (Camera, Arc<HittableList>, Vec3) {
         None,
     );
 
-    (cam, Arc::new(objects), Vec3::new(0.7, 0.8, 1.))
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![]),
+        Arc::new(background::Constant::new(Vec3::new(0.7, 0.8, 1.))),
+    )
 }
 
-pub fn two_perlin_spheres() -> (Camera, Arc<HittableList>, Vec3) {
+pub fn two_perlin_spheres() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
     let mut objects = HittableList::new(vec![]);
     /*
         let pertext = texture::Noise::new(4.);
@@ -179,16 +195,24 @@ pub fn two_perlin_spheres() -> (Camera, Arc<HittableList>, Vec3) {
         None,
     );
 
-    (cam, Arc::new(objects), Vec3::new(0.7, 0.8, 1.))
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![]),
+        Arc::new(background::Constant::new(Vec3::new(0.7, 0.8, 1.))),
+    )
 }
 
-pub fn globe() -> (Camera, Arc<HittableList>, Vec3) {
+pub fn globe() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
     let mut objects = HittableList::new(vec![]);
-    let globetex = texture::ImageTexture::new("earthmap.jpg");
+    let mut textures = texture::TextureManager::new();
+    let globetex = textures
+        .image("earthmap.jpg")
+        .expect("Couldn't load the globe texture");
     objects.add(Arc::new(Sphere {
         center: Vec3::new(0., 0., 0.),
         radius: 2.,
-        material: Arc::new(material::Lambertian::textured(Arc::new(globetex))),
+        material: Arc::new(material::Lambertian::textured(globetex)),
     }));
 
     /*
@@ -216,11 +240,16 @@ pub fn globe() -> (Camera, Arc<HittableList>, Vec3) {
         None,
     );
 
-    (cam, Arc::new(objects), Vec3::new(0.7, 0.8, 1.))
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![]),
+        Arc::new(background::Constant::new(Vec3::new(0.7, 0.8, 1.))),
+    )
 }
 
-pub fn simple_light() -> (Camera, Arc<HittableList>, Vec3) {
-    let background = Vec3::new(0., 0., 0.);
+pub fn simple_light() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
     let lookfrom = Vec3::new(26., 3., 6.);
     let lookat = Vec3::new(0., 2., 0.);
     let cam = Camera::new(
@@ -255,13 +284,385 @@ pub fn simple_light() -> (Camera, Arc<HittableList>, Vec3) {
     let difflight = Arc::new(material::DiffuseLight::new(Arc::new(
         texture::Solid::color_vec3(Vec3::new(4., 4., 4.)),
     )));
-    objects.add(Arc::new(XYRect::new(3., 5., 1., 3., -2., difflight)));
+    let light = Arc::new(light::AreaLight::new(Arc::new(XYRect::new(
+        3., 5., 1., 3., -2., difflight,
+    ))));
+    objects.add(light.clone());
+
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![light]),
+        background,
+    )
+}
+
+pub fn delta_lights() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0.02, 0.02, 0.03)));
+    let lookfrom = Vec3::new(13., 4., 6.);
+    let lookat = Vec3::new(0., 1., 0.);
+    let cam = Camera::new(
+        16. / 9.,
+        25.,
+        lookfrom,
+        lookat,
+        Vec3::new(0., 1., 0.),
+        0.0,
+        10.,
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., -1000., 0.),
+        radius: 1000.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 0.),
+        radius: 1.,
+        material: Arc::new(material::OrenNayar::new(Vec3::new(0.7, 0.3, 0.2), 0.5)),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., -2.5),
+        radius: 1.,
+        material: Arc::new(material::Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.1)),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 2.5),
+        radius: 1.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.2, 0.4, 0.7))),
+    }));
+
+    let mut lights = LightList::new(vec![]);
+    lights.add(Arc::new(light::SpotLight::new(
+        Vec3::new(3., 6., 2.5),
+        Vec3::new(0., 0., 2.5),
+        Vec3::new(1., 0.9, 0.7),
+        material::Power::Watts(150.),
+        15.,
+        25.,
+    )));
+    lights.add(Arc::new(light::PointLight::new(
+        Vec3::new(2., 3., -1.),
+        Vec3::new(1., 1., 1.),
+        material::Power::Lumens(100_000.),
+    )));
+    lights.add(Arc::new(light::DirectionalLight::new(
+        Vec3::new(-1., 2., 1.),
+        spectrum::blackbody(5800.),
+        light::Irradiance::WattsPerSquareMeter(1.),
+    )));
 
-    (cam, Arc::new(objects), background)
+    (cam, Arc::new(objects), lights, background)
 }
 
-pub fn cornell_box() -> (Camera, Arc<HittableList>, Vec3) {
-    let background = Vec3::new(0., 0., 0.);
+// a field of small glowing spheres, too many for every one of them to be sampled at each hit
+pub fn many_lights() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
+    let lookfrom = Vec3::new(0., 6., 16.);
+    let lookat = Vec3::new(0., 0., 0.);
+    let cam = Camera::new(
+        16. / 9.,
+        40.,
+        lookfrom,
+        lookat,
+        Vec3::new(0., 1., 0.),
+        0.0,
+        10.,
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+    let mut lights = LightList::new(vec![]);
+
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., -1000., 0.),
+        radius: 1000.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1.5, 0.),
+        radius: 1.5,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.8, 0.8, 0.8))),
+    }));
+
+    let mut glowing = HittableList::new(vec![]);
+    for a in -10..10 {
+        for b in -10..10 {
+            let radius = 0.1;
+            let center = Vec3::new(
+                a as f64 + 0.8 * utils::random_double(),
+                radius + 2. * utils::random_double(),
+                b as f64 + 0.8 * utils::random_double(),
+            );
+            let emitter = Arc::new(material::DiffuseLight::from_power(
+                spectrum::blackbody(utils::random_from_range(2000., 8000.)),
+                material::Power::Watts(2.),
+                4. * std::f64::consts::PI * radius * radius,
+                true,
+            ));
+            let light = Arc::new(light::AreaLight::new(Arc::new(Sphere::new(
+                center, radius, emitter,
+            ))));
+            glowing.add(light.clone());
+            lights.add(light);
+        }
+    }
+    objects.add(BVHNode::construct_tree(glowing, 0., 1.));
+
+    (cam, Arc::new(objects), lights, background)
+}
+
+// a rotationally symmetric downlight throwing most of its light sideways, nothing above the horizon
+const BATWING_IES: &str = "IESNA:LM-63-2002
+[TEST] batwing downlight
+[MANUFAC] none
+TILT=NONE
+1 1000 1 10 1 1 2 0.2 0.2 0
+1.0 1.0 15
+0 10 20 30 40 50 60 70 80 90
+0
+300 340 420 520 560 480 300 120 30 0
+";
+
+// a checker projected through a spot light, and a measured profile washing a wall from a point and a panel
+pub fn shaped_lights() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
+    let lookfrom = Vec3::new(0., 3., 12.);
+    let lookat = Vec3::new(0., 2., 0.);
+    let cam = Camera::new(
+        16. / 9.,
+        40.,
+        lookfrom,
+        lookat,
+        Vec3::new(0., 1., 0.),
+        0.0,
+        10.,
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+    let mut lights = LightList::new(vec![]);
+
+    let white = Arc::new(material::Lambertian::new(Vec3::new(0.7, 0.7, 0.7)));
+    objects.add(Arc::new(XZRect::new(
+        -10.,
+        10.,
+        -10.,
+        10.,
+        0.,
+        white.clone(),
+    )));
+    objects.add(Arc::new(XYRect::new(-10., 10., 0., 10., -3., white)));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(-2., 1., -1.),
+        radius: 1.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.2, 0.4, 0.7))),
+    }));
+
+    let gobo =
+        texture::UVChecker::from_vec3(Vec3::new_diagonal(1.), Vec3::new_diagonal(0.), 4., 4.);
+    lights.add(Arc::new(
+        light::SpotLight::new(
+            Vec3::new(-2., 6., 6.),
+            Vec3::new(-2., 3., -3.),
+            Vec3::new(1., 0.95, 0.9),
+            material::Power::Watts(20.),
+            15.,
+            20.,
+        )
+        .with_gobo(Arc::new(gobo)),
+    ));
+
+    let profile = Arc::new(IesProfile::parse(BATWING_IES).expect("Couldn't parse the ies profile"));
+    lights.add(Arc::new(light::PointLight::from_ies(
+        Vec3::new(3., 5., -2.5),
+        Vec3::new(3., 0., -2.5),
+        spectrum::blackbody(3000.),
+        profile.clone(),
+    )));
+
+    let panel = material::DiffuseLight::one_sided(Arc::new(texture::Solid::color_vec3(Vec3::new(
+        6., 6., 6.,
+    ))))
+    .with_profile(profile, Vec3::new(0., -1., 0.));
+    let panel = Arc::new(light::AreaLight::new(Arc::new(FlipFace::new(Arc::new(
+        XZRect::new(5., 6., -1., 0., 4., Arc::new(panel)),
+    )))));
+    objects.add(panel.clone());
+    lights.add(panel);
+
+    (cam, Arc::new(objects), lights, background)
+}
+
+// a technical illustration style view, with parallel edges staying parallel
+pub fn isometric() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0.25, 0.27, 0.3)));
+    let cam = Camera::orthographic(
+        16. / 9.,
+        12.,
+        Vec3::new(10., 10., 10.),
+        Vec3::new(0., 0., 0.),
+        Vec3::new(0., 1., 0.),
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+
+    let white = Arc::new(material::Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+    objects.add(Arc::new(XZRect::new(-4., 4., -4., 4., 0., white.clone())));
+    for (i, height) in [1., 2., 3., 1.5].iter().enumerate() {
+        let x = -3. + 2. * i as f64;
+        objects.add(Arc::new(Cuboid::new(
+            Vec3::new(x - 0.5, 0., -0.5),
+            Vec3::new(x + 0.5, *height, 0.5),
+            white.clone(),
+        )));
+    }
+    objects.add(Arc::new(Pyramid::new(
+        XZRect::new(-1., 1., 1.5, 3.5, 0., white),
+        Vec3::new(0., 2., 2.5),
+    )));
+
+    let lights = LightList::new(vec![Arc::new(light::DirectionalLight::new(
+        Vec3::new(-1., 3., 2.),
+        Vec3::new(1., 0.95, 0.9),
+        light::Irradiance::WattsPerSquareMeter(1.5),
+    ))]);
+
+    (cam, Arc::new(objects), lights, background)
+}
+
+pub fn environment_lighting() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let lookfrom = Vec3::new(13., 2., 3.);
+    let lookat = Vec3::new(0., 1., 0.);
+    let cam = Camera::new(
+        16. / 9.,
+        20.,
+        lookfrom,
+        lookat,
+        Vec3::new(0., 1., 0.),
+        0.0,
+        10.,
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., -1000., 0.),
+        radius: 1000.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., -2.2),
+        radius: 1.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.7, 0.3, 0.2))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 0.),
+        radius: 1.,
+        material: Arc::new(material::Dielectric::new(1.5)),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 2.2),
+        radius: 1.,
+        material: Arc::new(material::Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.)),
+    }));
+
+    // the environment is both the background and the only light
+    let environment = sunny_environment(512, 256);
+
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![]),
+        Arc::new(environment),
+    )
+}
+
+/*
+    an equirectangular map standing in for a captured one: a sky darkening towards the zenith,
+    a grey ground and a small sun much brighter than the rest, rows going from the zenith down
+*/
+fn sunny_environment(width: usize, height: usize) -> EnvironmentMap {
+    let sun = Vec3::new(-0.5, 0.6, 0.4).normalize();
+    let sun_cos_radius = utils::degrees_to_radians(2.).cos();
+    let mut texels = Vec::with_capacity(width * height);
+    for j in 0..height {
+        let elevation = PI / 2. - PI * (j as f64 + 0.5) / height as f64;
+        for i in 0..width {
+            let phi = 2. * PI * (i as f64 + 0.5) / width as f64 - PI;
+            let d = Vec3::new(
+                elevation.cos() * phi.cos(),
+                elevation.sin(),
+                -elevation.cos() * phi.sin(),
+            );
+            texels.push(if d.dot(sun) > sun_cos_radius {
+                Vec3::new(1000., 950., 850.)
+            } else if elevation < 0. {
+                Vec3::new(0.3, 0.28, 0.25)
+            } else {
+                let up = elevation.sin();
+                (1. - up) * Vec3::new(0.8, 0.85, 0.9) + up * Vec3::new(0.25, 0.45, 0.85)
+            });
+        }
+    }
+    EnvironmentMap::from_texels(width, height, texels, 0., 1.)
+}
+
+pub fn physical_sky() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let lookfrom = Vec3::new(13., 2., 3.);
+    let lookat = Vec3::new(0., 1., 0.);
+    let cam = Camera::new(
+        16. / 9.,
+        30.,
+        lookfrom,
+        lookat,
+        Vec3::new(0., 1., 0.),
+        0.0,
+        10.,
+        None,
+        None,
+    );
+    let mut objects = HittableList::new(vec![]);
+
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., -1000., 0.),
+        radius: 1000.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., -2.2),
+        radius: 1.,
+        material: Arc::new(material::Lambertian::new(Vec3::new(0.7, 0.3, 0.2))),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 0.),
+        radius: 1.,
+        material: Arc::new(material::Dielectric::new(1.5)),
+    }));
+    objects.add(Arc::new(Sphere {
+        center: Vec3::new(0., 1., 2.2),
+        radius: 1.,
+        material: Arc::new(material::Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.)),
+    }));
+
+    // late afternoon in early summer, somewhere in central europe
+    let sky = PreethamSky::at(170, 17., 48., 3.).with_intensity(0.03);
+
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![]),
+        Arc::new(sky),
+    )
+}
+
+pub fn cornell_box() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
     let lookfrom = Vec3::new(278., 278., -800.);
     let lookat = Vec3::new(278., 278., 0.);
     let cam = Camera::new(
@@ -281,7 +682,7 @@ pub fn cornell_box() -> (Camera, Arc<HittableList>, Vec3) {
     let red = Arc::new(material::Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
     let white = Arc::new(material::Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
     let green = Arc::new(material::Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(material::DiffuseLight::new(Arc::new(
+    let light = Arc::new(material::DiffuseLight::one_sided(Arc::new(
         texture::Solid::color_vec3(Vec3::new(20., 20., 20.)),
     )));
 
@@ -294,14 +695,10 @@ pub fn cornell_box() -> (Camera, Arc<HittableList>, Vec3) {
         green.clone(),
     )));
     objects.add(Arc::new(YZRect::new(0., 555., 0., 555., 0., red)));
-    objects.add(Arc::new(XZRect::new(
-        213.,
-        343.,
-        227.,
-        332.,
-        554.,
-        light.clone(),
-    )));
+    let light = Arc::new(light::AreaLight::new(Arc::new(FlipFace::new(Arc::new(
+        XZRect::new(213., 343., 227., 332., 554., light),
+    )))));
+    objects.add(light.clone());
 
     objects.add(Arc::new(XZRect::new(0., 555., 0., 555., 0., white.clone())));
     objects.add(Arc::new(XZRect::new(
@@ -373,11 +770,16 @@ pub fn cornell_box() -> (Camera, Arc<HittableList>, Vec3) {
     let cuboid2 = Arc::new(Translate::new(cuboid2, Vec3::new(140., 0., 65.)));
     objects.add(cuboid2);
 
-    (cam, Arc::new(objects), background)
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![light]),
+        background,
+    )
 }
 
-pub fn cornell_smoke() -> (Camera, Arc<HittableList>, Vec3) {
-    let background = Vec3::new(0., 0., 0.);
+pub fn cornell_smoke() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
     let lookfrom = Vec3::new(278., 278., -800.);
     let lookat = Vec3::new(278., 278., 0.);
     let cam = Camera::new(
@@ -397,13 +799,16 @@ pub fn cornell_smoke() -> (Camera, Arc<HittableList>, Vec3) {
     let red = Arc::new(material::Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
     let white = Arc::new(material::Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
     let green = Arc::new(material::Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(material::DiffuseLight::new(Arc::new(
+    let light = Arc::new(material::DiffuseLight::one_sided(Arc::new(
         texture::Solid::color_vec3(Vec3::new(7., 7., 7.)),
     )));
 
     objects.add(Arc::new(YZRect::new(0., 555., 0., 555., 555., green)));
     objects.add(Arc::new(YZRect::new(0., 555., 0., 555., 0., red)));
-    objects.add(Arc::new(XZRect::new(113., 443., 127., 432., 554., light)));
+    let light = Arc::new(light::AreaLight::new(Arc::new(FlipFace::new(Arc::new(
+        XZRect::new(113., 443., 127., 432., 554., light),
+    )))));
+    objects.add(light.clone());
     objects.add(Arc::new(XZRect::new(0., 555., 0., 555., 0., white.clone())));
     objects.add(Arc::new(XZRect::new(
         0.,
@@ -452,11 +857,16 @@ pub fn cornell_smoke() -> (Camera, Arc<HittableList>, Vec3) {
     ));
     objects.add(cuboid2);
 
-    (cam, Arc::new(objects), background)
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![light]),
+        background,
+    )
 }
 
-pub fn final_scene() -> (Camera, Arc<HittableList>, Vec3) {
-    let background = Vec3::new(0., 0., 0.);
+pub fn final_scene() -> (Camera, Arc<HittableList>, LightList, Arc<dyn Background>) {
+    let background = Arc::new(background::Constant::new(Vec3::new(0., 0., 0.)));
     let lookfrom = Vec3::new(478., 278., -600.);
     let lookat = Vec3::new(278., 278., 0.);
     let cam = Camera::new(
@@ -494,10 +904,13 @@ pub fn final_scene() -> (Camera, Arc<HittableList>, Vec3) {
 
     objects.add(BVHNode::construct_tree(boxes1, 0., 1.));
 
-    let light = Arc::new(material::DiffuseLight::new(Arc::new(
+    let light = Arc::new(material::DiffuseLight::one_sided(Arc::new(
         texture::Solid::color_vec3(Vec3::new(7., 7., 7.)),
     )));
-    objects.add(Arc::new(XZRect::new(123., 423., 147., 412., 554., light)));
+    let light = Arc::new(light::AreaLight::new(Arc::new(FlipFace::new(Arc::new(
+        XZRect::new(123., 423., 147., 412., 554., light),
+    )))));
+    objects.add(light.clone());
 
     let center1 = Vec3::new(400., 400., 200.);
     let center2 = center1 + Vec3::new(30., 0., 0.);
@@ -548,9 +961,12 @@ pub fn final_scene() -> (Camera, Arc<HittableList>, Vec3) {
         Arc::new(texture::Solid::color_vec3(Vec3::new(1., 1., 1.))),
     )));
 
-    let globe_material = Arc::new(material::Lambertian::textured(Arc::new(
-        texture::ImageTexture::new("earthmap.jpg"),
-    )));
+    let mut textures = texture::TextureManager::new();
+    let globe_material = Arc::new(material::Lambertian::textured(
+        textures
+            .image("earthmap.jpg")
+            .expect("Couldn't load the globe texture"),
+    ));
 
     objects.add(Arc::new(Sphere::new(
         Vec3::new(400., 200., 400.),
@@ -582,5 +998,10 @@ pub fn final_scene() -> (Camera, Arc<HittableList>, Vec3) {
         Vec3::new(-100., 270., 395.),
     )));
 
-    (cam, Arc::new(objects), background)
+    (
+        cam,
+        Arc::new(objects),
+        LightList::new(vec![light]),
+        background,
+    )
 }
@@ -0,0 +1,310 @@
+use super::{perlin, utils, Vec3};
+use rand::{rngs::StdRng, SeedableRng};
+
+/*
+    Simplex noise in 2, 3 and 4 dimensions, after
+    Gustavson - Simplex noise demystified
+    Output is roughly in [-1, 1]. The 4d variant is handy for animating a 3d pattern over time.
+*/
+
+const GRAD3: [[f64; 3]; 12] = [
+    [1., 1., 0.],
+    [-1., 1., 0.],
+    [1., -1., 0.],
+    [-1., -1., 0.],
+    [1., 0., 1.],
+    [-1., 0., 1.],
+    [1., 0., -1.],
+    [-1., 0., -1.],
+    [0., 1., 1.],
+    [0., -1., 1.],
+    [0., 1., -1.],
+    [0., -1., -1.],
+];
+
+const GRAD4: [[f64; 4]; 32] = [
+    [0., 1., 1., 1.],
+    [0., 1., 1., -1.],
+    [0., 1., -1., 1.],
+    [0., 1., -1., -1.],
+    [0., -1., 1., 1.],
+    [0., -1., 1., -1.],
+    [0., -1., -1., 1.],
+    [0., -1., -1., -1.],
+    [1., 0., 1., 1.],
+    [1., 0., 1., -1.],
+    [1., 0., -1., 1.],
+    [1., 0., -1., -1.],
+    [-1., 0., 1., 1.],
+    [-1., 0., 1., -1.],
+    [-1., 0., -1., 1.],
+    [-1., 0., -1., -1.],
+    [1., 1., 0., 1.],
+    [1., 1., 0., -1.],
+    [1., -1., 0., 1.],
+    [1., -1., 0., -1.],
+    [-1., 1., 0., 1.],
+    [-1., 1., 0., -1.],
+    [-1., -1., 0., 1.],
+    [-1., -1., 0., -1.],
+    [1., 1., 1., 0.],
+    [1., 1., -1., 0.],
+    [1., -1., 1., 0.],
+    [1., -1., -1., 0.],
+    [-1., 1., 1., 0.],
+    [-1., 1., -1., 0.],
+    [-1., -1., 1., 0.],
+    [-1., -1., -1., 0.],
+];
+
+pub struct Simplex {
+    // a permutation of 0..256, hash wraps every index into it
+    perm: Vec<usize>,
+}
+
+impl Simplex {
+    pub fn new() -> Self {
+        Simplex::seeded(perlin::DEFAULT_SEED)
+    }
+    pub fn seeded(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Simplex {
+            perm: utils::permutation(256, &mut rng),
+        }
+    }
+
+    fn hash(&self, i: i64) -> usize {
+        self.perm[(i & 255) as usize]
+    }
+
+    pub fn noise2(&self, x: f64, y: f64) -> f64 {
+        let f2 = 0.5 * (3f64.sqrt() - 1.);
+        let g2 = (3. - 3f64.sqrt()) / 6.;
+
+        // skew into the simplex grid to find the cell, then unskew back
+        let s = (x + y) * f2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * g2;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let corners = [
+            (x0, y0),
+            (x0 - i1 as f64 + g2, y0 - j1 as f64 + g2),
+            (x0 - 1. + 2. * g2, y0 - 1. + 2. * g2),
+        ];
+        let (i, j) = (i as i64, j as i64);
+        let gradients = [
+            self.hash(i + self.hash(j) as i64),
+            self.hash(i + i1 + self.hash(j + j1) as i64),
+            self.hash(i + 1 + self.hash(j + 1) as i64),
+        ];
+
+        let mut n = 0.;
+        for ((x, y), g) in corners.iter().zip(gradients.iter()) {
+            let t = 0.5 - x * x - y * y;
+            if t > 0. {
+                let g = GRAD3[g % 12];
+                n += t.powi(4) * (g[0] * x + g[1] * y);
+            }
+        }
+
+        70. * n
+    }
+
+    pub fn noise3(&self, p: &Vec3) -> f64 {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let f3 = 1. / 3.;
+        let g3 = 1. / 6.;
+
+        let s = (x + y + z) * f3;
+        let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+        let t = (i + j + k) * g3;
+        let (x0, y0, z0) = (x - (i - t), y - (j - t), z - (k - t));
+
+        // which of the six simplices of the skewed cube we are in
+        let (o1, o2) = if x0 >= y0 {
+            if y0 >= z0 {
+                ([1, 0, 0], [1, 1, 0])
+            } else if x0 >= z0 {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if y0 < z0 {
+            ([0, 0, 1], [0, 1, 1])
+        } else if x0 < z0 {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+
+        let offsets = [[0, 0, 0], o1, o2, [1, 1, 1]];
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        let mut n = 0.;
+        for (corner, o) in offsets.iter().enumerate() {
+            let g3_offset = corner as f64 * g3;
+            let x = x0 - o[0] as f64 + g3_offset;
+            let y = y0 - o[1] as f64 + g3_offset;
+            let z = z0 - o[2] as f64 + g3_offset;
+            let t = 0.6 - x * x - y * y - z * z;
+            if t > 0. {
+                let hash =
+                    self.hash(i + o[0] + self.hash(j + o[1] + self.hash(k + o[2]) as i64) as i64);
+                let g = GRAD3[hash % 12];
+                n += t.powi(4) * (g[0] * x + g[1] * y + g[2] * z);
+            }
+        }
+
+        32. * n
+    }
+
+    pub fn noise4(&self, p: &Vec3, w: f64) -> f64 {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let f4 = (5f64.sqrt() - 1.) / 4.;
+        let g4 = (5. - 5f64.sqrt()) / 20.;
+
+        let s = (x + y + z + w) * f4;
+        let cell = [
+            (x + s).floor(),
+            (y + s).floor(),
+            (z + s).floor(),
+            (w + s).floor(),
+        ];
+        let t = cell.iter().sum::<f64>() * g4;
+        let d0 = [
+            x - (cell[0] - t),
+            y - (cell[1] - t),
+            z - (cell[2] - t),
+            w - (cell[3] - t),
+        ];
+
+        // ranking the coordinates against each other picks the simplex and its traversal order
+        let mut rank = [0; 4];
+        for a in 0..4 {
+            for b in a + 1..4 {
+                if d0[a] > d0[b] {
+                    rank[a] += 1;
+                } else {
+                    rank[b] += 1;
+                }
+            }
+        }
+
+        let cell = [
+            cell[0] as i64,
+            cell[1] as i64,
+            cell[2] as i64,
+            cell[3] as i64,
+        ];
+        let mut n = 0.;
+        for corner in 0..5 {
+            // corner c steps along the axes ranked at least 4 - c
+            let mut offset = [0i64; 4];
+            for (axis, r) in rank.iter().enumerate() {
+                offset[axis] = (*r + corner >= 4) as i64;
+            }
+            let mut d = d0;
+            for (x, o) in d.iter_mut().zip(offset.iter()) {
+                *x += corner as f64 * g4 - *o as f64;
+            }
+            let t = 0.6 - d.iter().map(|x| x * x).sum::<f64>();
+            if t > 0. {
+                let hash = cell
+                    .iter()
+                    .zip(offset.iter())
+                    .rev()
+                    .fold(0, |hash, (c, o)| self.hash(c + o + hash as i64));
+                let g = GRAD4[hash % 32];
+                n += t.powi(4) * (g[0] * d[0] + g[1] * d[1] + g[2] * d[2] + g[3] * d[3]);
+            }
+        }
+
+        27. * n
+    }
+
+    // same octave sum as Perlin::turbulence, over the 4d noise so it evolves along w
+    pub fn turbulence4(&self, p: &Vec3, w: f64, octaves: u32) -> f64 {
+        let (mut accumulator, mut weight) = (0., 1.);
+        let (mut p_mut_copy, mut w_mut_copy) = (*p, w);
+        for _ in 0..octaves {
+            accumulator += weight * self.noise4(&p_mut_copy, w_mut_copy);
+            weight *= 0.5;
+            p_mut_copy *= 2.;
+            w_mut_copy *= 2.;
+        }
+
+        accumulator.abs()
+    }
+
+    // fractional brownian motion over the 3d noise, same parameters as Perlin::fbm
+    pub fn fbm(&self, p: &Vec3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let (mut accumulator, mut weight) = (0., 1.);
+        let mut p_mut_copy = *p;
+        for _ in 0..octaves {
+            accumulator += weight * self.noise3(&p_mut_copy);
+            weight *= gain;
+            p_mut_copy *= lacunarity;
+        }
+
+        accumulator
+    }
+
+    // the same sums over the 4d noise, w scaled along with p at each octave
+    pub fn fbm4(&self, p: &Vec3, w: f64, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let (mut accumulator, mut weight) = (0., 1.);
+        let (mut p_mut_copy, mut w_mut_copy) = (*p, w);
+        for _ in 0..octaves {
+            accumulator += weight * self.noise4(&p_mut_copy, w_mut_copy);
+            weight *= gain;
+            p_mut_copy *= lacunarity;
+            w_mut_copy *= lacunarity;
+        }
+
+        accumulator
+    }
+
+    pub fn ridged4(
+        &self,
+        p: &Vec3,
+        w: f64,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+        offset: f64,
+    ) -> f64 {
+        let (mut accumulator, mut weight, mut amplitude) = (0., 1., 1.);
+        let (mut p_mut_copy, mut w_mut_copy) = (*p, w);
+        for _ in 0..octaves {
+            let mut signal = offset - self.noise4(&p_mut_copy, w_mut_copy).abs();
+            signal = signal * signal * weight;
+            accumulator += amplitude * signal;
+            weight = utils::clamp(signal * 2., 0., 1.);
+            amplitude *= gain;
+            p_mut_copy *= lacunarity;
+            w_mut_copy *= lacunarity;
+        }
+
+        accumulator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simplex_is_seeded_and_bounded() {
+        let (a, b) = (Simplex::seeded(3), Simplex::seeded(3));
+        for i in 0..1000 {
+            let p = Vec3::new(0.37 * i as f64, -0.11 * i as f64, 0.05 * i as f64);
+            let w = 0.23 * i as f64;
+            let values = [a.noise2(p.x(), p.y()), a.noise3(&p), a.noise4(&p, w)];
+            assert_eq!(values[1], b.noise3(&p));
+            for value in values.iter() {
+                assert!(value.abs() <= 1.05, "{}", value);
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use super::{
+    light::{Light, LightSample},
+    material::Power,
+    onb::Onb,
+    spectrum, utils, Color, Direction, Position, Vec3,
+};
+use std::f64::consts::PI;
+
+/*
+    Analytic daylight after
+    Preetham, Shirley & Smits - A Practical Analytic Model for Daylight
+    The sky is evaluated in CIE xyY from Perez distributions scaled to the zenith values,
+    the sun is a disk whose color comes from the extraterrestrial sunlight attenuated
+    by Rayleigh and aerosol scattering along the air mass.
+    Radiance is in W/(sr m^2), using the same 683 lm/W as the other physical units.
+    Below the horizon it's black, the ground is expected to be geometry.
+*/
+
+// angular radius of the sun disk
+const SUN_ANGULAR_RADIUS: f64 = 0.2665 * PI / 180.;
+// illuminance of the sun outside the atmosphere, lux
+const SOLAR_ILLUMINANCE: f64 = 128_000.;
+// chance of sampling the sun disk rather than the sky when it's above the horizon
+const SUN_SAMPLING_PROBABILITY: f64 = 0.5;
+
+// Perez et al. all weather luminance distribution
+#[derive(Copy, Clone)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    // theta is the view zenith angle, gamma the angle to the sun
+    fn f(&self, theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1. + self.a * (self.b / theta.cos().max(0.01)).exp())
+            * (1. + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+pub struct PreethamSky {
+    // towards the sun
+    sun_direction: Direction,
+    theta_sun: f64,
+    // zenith values in xyY, Y in kcd/m^2
+    zenith: Vec3,
+    perez: [Perez; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    intensity: f64,
+}
+
+impl PreethamSky {
+    // turbidity goes from 2 (very clear) to about 10 (hazy)
+    pub fn new(sun_direction: Direction, turbidity: f64) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        // the model only holds for the sun above the horizon
+        let theta_sun = utils::clamp(sun_direction.y(), 0., 1.).acos();
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let powers = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.];
+        let chromaticity = |c2: [f64; 4], c1: [f64; 4], c0: [f64; 4]| {
+            (0..4)
+                .map(|i| (t * t * c2[i] + t * c1[i] + c0[i]) * powers[i])
+                .sum::<f64>()
+        };
+        let zenith_x = chromaticity(
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        );
+        let zenith_y = chromaticity(
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        );
+
+        let perez = [
+            Perez {
+                a: -0.0193 * t - 0.2592,
+                b: -0.0665 * t + 0.0008,
+                c: -0.0004 * t + 0.2125,
+                d: -0.0641 * t - 0.8989,
+                e: -0.0033 * t + 0.0452,
+            },
+            Perez {
+                a: -0.0167 * t - 0.2608,
+                b: -0.0950 * t + 0.0092,
+                c: -0.0079 * t + 0.2102,
+                d: -0.0441 * t - 1.6537,
+                e: -0.0109 * t + 0.0529,
+            },
+            Perez {
+                a: 0.1787 * t - 1.4630,
+                b: -0.3554 * t + 0.4275,
+                c: -0.0227 * t + 5.3251,
+                d: 0.1206 * t - 2.5771,
+                e: -0.0670 * t + 0.3703,
+            },
+        ];
+
+        PreethamSky {
+            sun_direction,
+            theta_sun,
+            zenith: Vec3::new(zenith_x, zenith_y, zenith_luminance.max(0.)),
+            perez,
+            sun_radiance: PreethamSky::sun_radiance(sun_direction, turbidity),
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+            intensity: 1.,
+        }
+    }
+
+    /*
+        sun position for a day of the year (1 to 365), local solar time in hours and
+        latitude in degrees, north positive. x points east and -z points north
+    */
+    pub fn at(day_of_year: u32, solar_time: f64, latitude: f64, turbidity: f64) -> Self {
+        let declination =
+            utils::degrees_to_radians(23.44) * (2. * PI * (284. + day_of_year as f64) / 365.).sin();
+        let hour_angle = utils::degrees_to_radians(15. * (solar_time - 12.));
+        let latitude = utils::degrees_to_radians(latitude);
+
+        let sin_altitude = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        let cos_altitude = (1. - sin_altitude * sin_altitude).max(0.).sqrt();
+        // azimuth from north towards east
+        let cos_azimuth = utils::clamp(
+            (declination.sin() - sin_altitude * latitude.sin())
+                / (cos_altitude * latitude.cos()).max(1e-9),
+            -1.,
+            1.,
+        );
+        let azimuth = if hour_angle > 0. {
+            2. * PI - cos_azimuth.acos()
+        } else {
+            cos_azimuth.acos()
+        };
+
+        PreethamSky::new(
+            Vec3::new(
+                cos_altitude * azimuth.sin(),
+                sin_altitude,
+                -cos_altitude * azimuth.cos(),
+            ),
+            turbidity,
+        )
+    }
+
+    // scales both sky and sun, the physical values are far brighter than typical scene lights
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn sun_radiance(sun_direction: Direction, turbidity: f64) -> Color {
+        if sun_direction.y() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        // relative optical air mass, Kasten's formula
+        let theta = sun_direction.y().acos();
+        let air_mass =
+            1. / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).max(0.01).powf(-1.253));
+
+        // angstrom's turbidity formula for the aerosols, rayleigh scattering for the molecules
+        let beta = 0.04608 * turbidity - 0.04586;
+        let transmittance = |lambda_um: f64| {
+            let rayleigh = 0.008735 * lambda_um.powf(-4.08);
+            let aerosol = beta * lambda_um.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        };
+
+        let solid_angle = 2. * PI * (1. - SUN_ANGULAR_RADIUS.cos());
+        let radiance = SOLAR_ILLUMINANCE / Power::LUMENS_PER_WATT / solid_angle;
+        let extraterrestrial = spectrum::blackbody(5778.);
+        radiance
+            * Color::new(
+                extraterrestrial.r() * transmittance(0.68),
+                extraterrestrial.g() * transmittance(0.55),
+                extraterrestrial.b() * transmittance(0.44),
+            )
+    }
+
+    pub fn radiance(&self, direction: &Direction) -> Color {
+        let d = direction.normalize();
+        if d.y() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        let theta = d.y().acos();
+        let cos_gamma = utils::clamp(d.dot(self.sun_direction), -1., 1.);
+        let gamma = cos_gamma.acos();
+
+        let value = |i: usize, zenith: f64| {
+            zenith * self.perez[i].f(theta, gamma) / self.perez[i].f(0., self.theta_sun)
+        };
+        let (x, y) = (value(0, self.zenith.x()), value(1, self.zenith.y()));
+        // kcd/m^2 to W/(sr m^2)
+        let luminance = value(2, self.zenith.z()) * 1000. / Power::LUMENS_PER_WATT;
+
+        let sky = spectrum::xyz_to_rgb(Vec3::new(
+            x / y * luminance,
+            luminance,
+            (1. - x - y) / y * luminance,
+        ));
+        let sun = if cos_gamma >= self.cos_sun_radius {
+            self.sun_radiance
+        } else {
+            Color::new(0., 0., 0.)
+        };
+
+        self.intensity * (Color::new(sky.r().max(0.), sky.g().max(0.), sky.b().max(0.)) + sun)
+    }
+
+    fn sun_pdf(&self, direction: &Direction) -> f64 {
+        if direction.dot(self.sun_direction) >= self.cos_sun_radius {
+            1. / (2. * PI * (1. - self.cos_sun_radius))
+        } else {
+            0.
+        }
+    }
+
+    // uniform direction within the sun disk
+    fn sample_sun(&self) -> Direction {
+        let cos_theta = 1. - utils::random_double() * (1. - self.cos_sun_radius);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * utils::random_double();
+        Onb::from_w(self.sun_direction).local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+impl Light for PreethamSky {
+    // a mix of the sun disk and the upper hemisphere, weighted by the pdf of both
+    fn sample(&self, _p: &Position) -> Option<LightSample> {
+        let sun_probability = if self.sun_direction.y() > 0. {
+            SUN_SAMPLING_PROBABILITY
+        } else {
+            0.
+        };
+
+        let direction = if utils::random_double() < sun_probability {
+            self.sample_sun()
+        } else {
+            let cos_theta = utils::random_double();
+            let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+            let phi = 2. * PI * utils::random_double();
+            Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+        };
+        if direction.y() <= 0. {
+            return None;
+        }
+
+        let pdf = sun_probability * self.sun_pdf(&direction) + (1. - sun_probability) / (2. * PI);
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            irradiance: self.radiance(&direction) / pdf,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_is_brighter_around_the_sun() {
+        let sky = PreethamSky::new(Vec3::new(0., 1., -1.), 3.);
+        let towards_sun = sky.radiance(&Vec3::new(0., 1., -1.2));
+        let away_from_sun = sky.radiance(&Vec3::new(0., 1., 1.2));
+        assert!(towards_sun.luminance() > away_from_sun.luminance());
+        // rayleigh scattering makes the clear sky blue
+        assert!(away_from_sun.b() > away_from_sun.r());
+        assert_eq!(sky.radiance(&Vec3::new(0., -1., 0.)).luminance(), 0.);
+
+        // equinox noon at the equator puts the sun straight up
+        let noon = PreethamSky::at(80, 12., 0., 3.);
+        assert!(noon.sun_direction.y() > 0.99);
+        let morning = PreethamSky::at(80, 8., 45., 3.);
+        assert!(morning.sun_direction.x() > 0. && morning.sun_direction.y() > 0.);
+    }
+}
@@ -0,0 +1,298 @@
+use super::{Color, Vec3};
+use std::ops;
+
+/*
+    Spectral mode machinery: hero wavelength sampling, RGB to spectrum upsampling (Smits' method)
+    and conversion from sampled spectra to linear sRGB through CIE XYZ.
+*/
+
+pub const N_WAVELENGTHS: usize = 4;
+pub const LAMBDA_MIN: f64 = 380.;
+pub const LAMBDA_MAX: f64 = 720.;
+
+// integral of the Y matching function below over the visible range
+const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+// the equal-energy white of a flat spectrum is mapped onto the D65 white point of sRGB
+const D65_WHITE: [f64; 3] = [0.95047, 1., 1.08883];
+
+pub struct Wavelengths {
+    lambda: [f64; N_WAVELENGTHS],
+    pdf: [f64; N_WAVELENGTHS],
+}
+
+impl Wavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        /*
+            hero wavelength sampling: the remaining wavelengths are evenly spaced rotations
+            of the hero, so that one random number covers the whole visible range
+        */
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.; N_WAVELENGTHS];
+        lambda[0] = LAMBDA_MIN + u * range;
+        for i in 1..N_WAVELENGTHS {
+            lambda[i] = lambda[0] + i as f64 * range / N_WAVELENGTHS as f64;
+            if lambda[i] > LAMBDA_MAX {
+                lambda[i] -= range;
+            }
+        }
+
+        Wavelengths {
+            lambda,
+            pdf: [1. / range; N_WAVELENGTHS],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.)
+    }
+
+    // used when the path depends on the wavelength, e.g. refraction through a dispersive medium
+    pub fn terminate_secondary(&mut self) {
+        if self.is_secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf[1..].iter_mut() {
+            *pdf = 0.;
+        }
+        self.pdf[0] /= N_WAVELENGTHS as f64;
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Spectrum {
+    s: [f64; N_WAVELENGTHS],
+}
+
+impl Spectrum {
+    pub fn new(value: f64) -> Self {
+        Spectrum {
+            s: [value; N_WAVELENGTHS],
+        }
+    }
+
+    pub fn from_rgb(rgb: Color, lambdas: &Wavelengths) -> Self {
+        let mut s = [0.; N_WAVELENGTHS];
+        for (i, value) in s.iter_mut().enumerate() {
+            *value = rgb_to_spectrum(rgb, lambdas.lambda(i));
+        }
+        Spectrum { s }
+    }
+
+    pub fn to_xyz(self, lambdas: &Wavelengths) -> Vec3 {
+        let mut xyz = Vec3::new(0., 0., 0.);
+        for i in 0..N_WAVELENGTHS {
+            if lambdas.pdf[i] == 0. {
+                continue;
+            }
+            let lambda = lambdas.lambda(i);
+            xyz += (self.s[i] / lambdas.pdf[i])
+                * Vec3::new(cie_x(lambda), cie_y(lambda), cie_z(lambda));
+        }
+
+        xyz / (N_WAVELENGTHS as f64 * CIE_Y_INTEGRAL)
+    }
+
+    pub fn to_rgb(self, lambdas: &Wavelengths) -> Color {
+        let xyz = self.to_xyz(lambdas);
+        xyz_to_rgb(Vec3::new(
+            xyz.x() * D65_WHITE[0],
+            xyz.y() * D65_WHITE[1],
+            xyz.z() * D65_WHITE[2],
+        ))
+    }
+}
+
+pub fn xyz_to_rgb(xyz: Vec3) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// planck's law, lambda in nm
+fn planck(lambda: f64, kelvin: f64) -> f64 {
+    const C: f64 = 299792458.;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+    let l = lambda * 1e-9;
+    (2. * H * C * C) / (l.powi(5) * (((H * C) / (l * KB * kelvin)).exp() - 1.))
+}
+
+// linear sRGB color of a black body at the given temperature, normalized to a luminance of 1
+pub fn blackbody(kelvin: f64) -> Color {
+    let mut xyz = Vec3::new(0., 0., 0.);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        xyz += planck(lambda, kelvin) * Vec3::new(cie_x(lambda), cie_y(lambda), cie_z(lambda));
+        lambda += 1.;
+    }
+    let rgb = xyz_to_rgb(xyz / xyz.y());
+
+    // temperatures far from the sRGB white point end up slightly out of gamut
+    Color::new(rgb.r().max(0.), rgb.g().max(0.), rgb.b().max(0.))
+}
+
+impl ops::Add for Spectrum {
+    type Output = Spectrum;
+    fn add(self, _rhs: Spectrum) -> Spectrum {
+        let mut s = self.s;
+        for (a, b) in s.iter_mut().zip(_rhs.s.iter()) {
+            *a += b;
+        }
+        Spectrum { s }
+    }
+}
+
+impl ops::Mul for Spectrum {
+    type Output = Spectrum;
+    fn mul(self, _rhs: Spectrum) -> Spectrum {
+        let mut s = self.s;
+        for (a, b) in s.iter_mut().zip(_rhs.s.iter()) {
+            *a *= b;
+        }
+        Spectrum { s }
+    }
+}
+
+impl ops::Index<usize> for Spectrum {
+    type Output = f64;
+    fn index(&self, i: usize) -> &f64 {
+        &self.s[i]
+    }
+}
+
+/*
+    CIE 1931 matching functions, multi-lobe gaussian fit from
+    Wyman, Sloan & Shirley - Simple Analytic Approximations to the CIE XYZ Color Matching Functions
+*/
+
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+fn cie_x(lambda: f64) -> f64 {
+    1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+fn cie_y(lambda: f64) -> f64 {
+    0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+fn cie_z(lambda: f64) -> f64 {
+    1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+/*
+    Smits - An RGB to Spectrum Conversion for Reflectances
+    ten bins over the visible range
+*/
+
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = (((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.) as usize).min(9);
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+
+    if r <= g && r <= b {
+        r * SMITS_WHITE[bin]
+            + if g <= b {
+                (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+            } else {
+                (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin]
+            + if r <= b {
+                (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+            } else {
+                (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+            }
+    } else {
+        b * SMITS_WHITE[bin]
+            + if r <= g {
+                (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+            } else {
+                (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_round_trip() {
+        const N: usize = 1000;
+        for rgb in [
+            Color::new(1., 1., 1.),
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(0.1, 0.3, 0.7),
+        ]
+        .iter()
+        {
+            let mut average = Color::new(0., 0., 0.);
+            for i in 0..N {
+                let lambdas = Wavelengths::sample_uniform((i as f64 + 0.5) / N as f64);
+                average += Spectrum::from_rgb(*rgb, &lambdas).to_rgb(&lambdas) / N as f64;
+            }
+            // smits' upsampling only roughly round trips saturated colors
+            for c in 0..3 {
+                assert!(
+                    (average[c] - rgb[c]).abs() < 0.1,
+                    "{:?} -> {:?}",
+                    rgb,
+                    average
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blackbody_colors() {
+        let candle = blackbody(1900.);
+        assert!(candle.r() > candle.g() && candle.g() > candle.b());
+        let daylight = blackbody(6504.);
+        for c in 0..3 {
+            assert!((daylight[c] - 1.).abs() < 0.05, "{:?}", daylight);
+        }
+        let sky = blackbody(12000.);
+        assert!(sky.b() > sky.r());
+    }
+}
@@ -1,4 +1,8 @@
-use super::{HitRecord, Hittable, Material, Ray, Vec3, AABB, Arc};
+use super::{
+    hittable, onb::Onb, ray::RayKind, utils, Arc, Direction, HitRecord, Hittable, Material,
+    Position, Ray, Vec3, AABB,
+};
+use std::f64::consts::PI;
 
 pub struct Sphere {
     pub center: Vec3,
@@ -14,6 +18,20 @@ impl Sphere {
             material,
         }
     }
+    // derivatives of the point on the sphere with respect to get_sphere_uv's (u, v), n being the outward normal
+    pub fn get_sphere_partials(n: Vec3, radius: f64) -> (Vec3, Vec3) {
+        let pi = std::f64::consts::PI;
+        let sin_theta = (1. - n.y() * n.y()).max(0.).sqrt().max(1e-8);
+        (
+            2. * pi * radius * Vec3::new(n.z(), 0., -n.x()),
+            pi * radius
+                * Vec3::new(
+                    -n.x() * n.y() / sin_theta,
+                    sin_theta,
+                    -n.y() * n.z() / sin_theta,
+                ),
+        )
+    }
     pub fn get_sphere_uv(p: Vec3) -> (f64, f64) {
         let theta = (-p.y()).acos();
         let phi = (-p.z()).atan2(p.x()) + std::f64::consts::PI;
@@ -23,6 +41,16 @@ impl Sphere {
             theta / std::f64::consts::PI,
         )
     }
+
+    // cosine of the half angle of the cone the sphere covers seen from outside, None from inside
+    fn cos_theta_max(&self, origin: &Position) -> Option<f64> {
+        let distance_squared = (self.center - *origin).squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1. - radius_squared / distance_squared).max(0.).sqrt())
+    }
 }
 
 impl Hittable for Sphere {
@@ -46,16 +74,12 @@ impl Hittable for Sphere {
 
         let outward_normal = (r.point(root) - self.center) / self.radius;
         let (u, v) = Sphere::get_sphere_uv(outward_normal);
+        let (dpdu, dpdv) = Sphere::get_sphere_partials(outward_normal, self.radius);
 
-        Some(HitRecord::new(
-            root,
-            u,
-            v,
-            r.point(root),
-            outward_normal,
-            r,
-            &self.material,
-        ))
+        Some(
+            HitRecord::new(root, u, v, r.point(root), outward_normal, r, &self.material)
+                .with_partials(dpdu, dpdv),
+        )
     }
 
     fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
@@ -64,8 +88,50 @@ impl Hittable for Sphere {
             self.center + Vec3::new_diagonal(self.radius),
         ))
     }
+
+    fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        let normal = Vec3::random_unit_vector();
+        Some((self.center + self.radius * normal, normal))
+    }
+
+    // uniform over the cone of directions the sphere covers, falling back to area sampling from inside
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => {
+                let (point, normal) = self.sample_area()?;
+                return hittable::area_to_solid_angle(origin, &point, &normal, self.area());
+            }
+        };
+        let cos_theta = 1. - utils::random_double() * (1. - cos_theta_max);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * utils::random_double();
+        let direction = Onb::from_w(self.center - *origin).local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        Some((direction, 1. / (2. * PI * (1. - cos_theta_max))))
+    }
+
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        let ray = Ray::new(*origin, *direction, None).with_kind(RayKind::Light);
+        let hit = match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.,
+        };
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1. / (2. * PI * (1. - cos_theta_max)),
+            None => hittable::hit_to_solid_angle(&hit, direction, self.area()),
+        }
+    }
 }
 
+// can't be an AreaLight, it has no fixed surface to sample lights on
 pub struct MovingSphere {
     center0: Vec3,
     center1: Vec3,
@@ -117,17 +183,16 @@ impl Hittable for MovingSphere {
             }
         }
 
-        let outward_normal = (r.point(root) - self.center(r.time())) / self.radius;
+        let center = self.center(r.time());
+        let outward_normal = (r.point(root) - center) / self.radius;
         let (u, v) = Sphere::get_sphere_uv(outward_normal);
-        Some(HitRecord::new(
-            root,
-            u,
-            v,
-            r.point(root),
-            outward_normal,
-            r,
-            &self.material,
-        ))
+        let (dpdu, dpdv) = Sphere::get_sphere_partials(outward_normal, self.radius);
+        // relative to the center, so object space patterns travel with the sphere
+        Some(
+            HitRecord::new(root, u, v, r.point(root), outward_normal, r, &self.material)
+                .with_partials(dpdu, dpdv)
+                .with_object_point(r.point(root) - center),
+        )
     }
 
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
@@ -143,3 +208,47 @@ impl Hittable for MovingSphere {
         Some(AABB::surrounding_box(box0, box1))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian,
+        texture::{Checker, Texture},
+    };
+
+    #[test]
+    fn object_space_patterns_stick_to_moving_spheres() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1.3, 0., 0.),
+            1.,
+            Arc::new(Lambertian::new(Vec3::new_diagonal(0.5))),
+            0.,
+            1.,
+        );
+        let checker = Checker::from_vec3(Vec3::new_diagonal(0.), Vec3::new_diagonal(1.))
+            .with_frequency(10.)
+            .in_object_space();
+
+        // the same point of the sphere at both ends of the shutter interval
+        let direction = Vec3::new(0.3, -1., 0.2);
+        let hit_at = |time: f64| {
+            let origin = sphere.center(time) - 5. * direction;
+            sphere
+                .hit(
+                    &Ray::new(origin, direction, Some(time)),
+                    0.001,
+                    f64::INFINITY,
+                )
+                .unwrap()
+        };
+        let (start, end) = (hit_at(0.), hit_at(1.));
+        assert!((end.p - start.p - Vec3::new(1.3, 0., 0.)).length() < 1e-9);
+        assert!((end.object_p - start.object_p).length() < 1e-9);
+        assert_eq!(
+            checker.sample(&start.texture_point()).r(),
+            checker.sample(&end.texture_point()).r()
+        );
+    }
+}
@@ -1,9 +1,62 @@
-use super::{utils, Arc, Perlin, Vec3};
+use super::{simplex::Simplex, utils, Arc, Perlin, Position, Vec3};
+use exr::prelude::read_first_rgba_layer_from_file;
+use image::codecs::hdr::HdrDecoder;
 use image::io::Reader as ImageReader;
-use image::GenericImageView;
+use image::ImageError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 
 pub trait Texture: Send + Sync {
-    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3;
+    fn sample(&self, point: &TexturePoint) -> Vec3;
+    // for callers that only have the uv coordinates and the hit point
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        self.sample(&TexturePoint::new(u, v, *p))
+    }
+}
+
+// everything a texture can be evaluated with
+#[derive(Copy, Clone)]
+pub struct TexturePoint {
+    pub u: f64,
+    pub v: f64,
+    pub p: Position,
+    // p before any Translate/RotateY instance got applied
+    pub object_p: Position,
+    pub time: f64,
+    // None when the ray carries no differentials, e.g. after a diffuse bounce
+    pub footprint: Option<Footprint>,
+}
+
+impl TexturePoint {
+    pub fn new(u: f64, v: f64, p: Position) -> Self {
+        TexturePoint {
+            u,
+            v,
+            p,
+            object_p: p,
+            time: 0.,
+            footprint: None,
+        }
+    }
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+}
+
+// how far p, u and v move between neighbouring pixels
+#[derive(Copy, Clone)]
+pub struct Footprint {
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
 }
 
 pub struct Solid {
@@ -22,42 +75,200 @@ impl Solid {
 }
 
 impl Texture for Solid {
-    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Vec3 {
+    fn sample(&self, _point: &TexturePoint) -> Vec3 {
         self.color
     }
 }
 
+// 3d checker, the checks are pi / frequency wide
 pub struct Checker {
     odd: Arc<dyn Texture>,
     even: Arc<dyn Texture>,
+    frequency: f64,
+    object_space: bool,
 }
 
 impl Checker {
     pub fn new(odd: Arc<dyn Texture>, even: Arc<dyn Texture>) -> Self {
-        Checker { odd, even }
-    }
-    pub fn from_vec3(c1: Vec3, c2: Vec3) -> Self {
         Checker {
-            even: Arc::new(Solid::color_vec3(c1)),
-            odd: Arc::new(Solid::color_vec3(c2)),
+            odd,
+            even,
+            frequency: 10.,
+            object_space: false,
         }
     }
+    pub fn from_vec3(c1: Vec3, c2: Vec3) -> Self {
+        Checker::new(
+            Arc::new(Solid::color_vec3(c2)),
+            Arc::new(Solid::color_vec3(c1)),
+        )
+    }
+    pub fn with_frequency(mut self, frequency: f64) -> Self {
+        self.frequency = frequency;
+        self
+    }
+    // evaluated before Translate/RotateY, so the pattern moves along with the object
+    pub fn in_object_space(mut self) -> Self {
+        self.object_space = true;
+        self
+    }
 }
 
 impl Texture for Checker {
-    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
-        let sines = (10. * p.x()).sin() * (10. * p.y()).sin() * (10. * p.z()).sin();
-        if sines < 0. {
-            self.odd.value(u, v, &p)
-        } else {
-            self.even.value(u, v, &p)
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let p = self.frequency
+            * if self.object_space {
+                point.object_p
+            } else {
+                point.p
+            };
+        let sines = p.x().sin() * p.y().sin() * p.z().sin();
+        let (odd, even) = (self.odd.sample(point), self.even.sample(point));
+        let point_sampled = if sines < 0. { odd } else { even };
+
+        /*
+            fades into the average of both once the footprint covers about a whole check,
+            where point sampling would only produce aliasing.
+            instances only rotate and translate, so the world space footprint fits object space too
+        */
+        let checks = point.footprint.map_or(0., |f| {
+            f.dpdx.length().max(f.dpdy.length()) * self.frequency / std::f64::consts::PI
+        });
+        let blend = utils::clamp(2. * checks - 1., 0., 1.);
+
+        (1. - blend) * point_sampled + blend * 0.5 * (odd + even)
+    }
+}
+
+// how many uv cells of the given frequencies the footprint spans, 0 without a footprint
+fn uv_cells_in_footprint(point: &TexturePoint, frequency_u: f64, frequency_v: f64) -> f64 {
+    point.footprint.map_or(0., |f| {
+        (f.dudx.abs() * frequency_u)
+            .max(f.dudy.abs() * frequency_u)
+            .max(f.dvdx.abs() * frequency_v)
+            .max(f.dvdy.abs() * frequency_v)
+    })
+}
+
+// checker laid out along the surface parametrization, frequency is the number of checks per unit uv
+pub struct UVChecker {
+    odd: Arc<dyn Texture>,
+    even: Arc<dyn Texture>,
+    frequency_u: f64,
+    frequency_v: f64,
+}
+
+impl UVChecker {
+    pub fn new(
+        odd: Arc<dyn Texture>,
+        even: Arc<dyn Texture>,
+        frequency_u: f64,
+        frequency_v: f64,
+    ) -> Self {
+        UVChecker {
+            odd,
+            even,
+            frequency_u,
+            frequency_v,
         }
     }
+    pub fn from_vec3(c1: Vec3, c2: Vec3, frequency_u: f64, frequency_v: f64) -> Self {
+        UVChecker::new(
+            Arc::new(Solid::color_vec3(c2)),
+            Arc::new(Solid::color_vec3(c1)),
+            frequency_u,
+            frequency_v,
+        )
+    }
+}
+
+impl Texture for UVChecker {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        let cell = (point.u * self.frequency_u).floor() + (point.v * self.frequency_v).floor();
+        let (odd, even) = (self.odd.sample(point), self.even.sample(point));
+        let point_sampled = if cell.rem_euclid(2.) == 1. { odd } else { even };
+
+        let cells = uv_cells_in_footprint(point, self.frequency_u, self.frequency_v);
+        let blend = utils::clamp(2. * cells - 1., 0., 1.);
+
+        (1. - blend) * point_sampled + blend * 0.5 * (odd + even)
+    }
+}
+
+/*
+    lines along constant u and constant v, frequency is the number of cells per unit uv
+    and line_width the fraction of a cell covered by each line
+*/
+pub struct Grid {
+    line: Arc<dyn Texture>,
+    background: Arc<dyn Texture>,
+    frequency_u: f64,
+    frequency_v: f64,
+    line_width: f64,
+}
+
+impl Grid {
+    pub fn new(
+        line: Arc<dyn Texture>,
+        background: Arc<dyn Texture>,
+        frequency_u: f64,
+        frequency_v: f64,
+        line_width: f64,
+    ) -> Self {
+        Grid {
+            line,
+            background,
+            frequency_u,
+            frequency_v,
+            line_width: utils::clamp(line_width, 0., 1.),
+        }
+    }
+    pub fn from_vec3(
+        line: Vec3,
+        background: Vec3,
+        frequency_u: f64,
+        frequency_v: f64,
+        line_width: f64,
+    ) -> Self {
+        Grid::new(
+            Arc::new(Solid::color_vec3(line)),
+            Arc::new(Solid::color_vec3(background)),
+            frequency_u,
+            frequency_v,
+            line_width,
+        )
+    }
+}
+
+impl Texture for Grid {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        // distance to the closest line, in cells
+        let distance = |x: f64| {
+            let f = x.rem_euclid(1.);
+            f.min(1. - f)
+        };
+        let half_width = 0.5 * self.line_width;
+        let on_line = distance(point.u * self.frequency_u) < half_width
+            || distance(point.v * self.frequency_v) < half_width;
+
+        let (line, background) = (self.line.sample(point), self.background.sample(point));
+        let point_sampled = if on_line { line } else { background };
+
+        // from afar the grid averages to its line coverage
+        let coverage = 1. - (1. - self.line_width) * (1. - self.line_width);
+        let average = coverage * line + (1. - coverage) * background;
+        let cells = uv_cells_in_footprint(point, self.frequency_u, self.frequency_v);
+        let blend = utils::clamp(2. * cells - 1., 0., 1.);
+
+        (1. - blend) * point_sampled + blend * average
+    }
 }
 
 pub struct Noise {
     noise: Perlin,
     scale: f64,
+    // 4d noise and its speed along the time axis, None for a static pattern
+    evolution: Option<(Simplex, f64)>,
 }
 
 impl Noise {
@@ -65,75 +276,683 @@ impl Noise {
         Noise {
             noise: Perlin::new(),
             scale,
+            evolution: None,
+        }
+    }
+    // the marble pattern changes shape over time, speed is in noise cells per unit time
+    pub fn animated(scale: f64, speed: f64) -> Self {
+        Noise {
+            evolution: Some((Simplex::new(), speed)),
+            ..Noise::new(scale)
         }
     }
+    // octaves summed by the turbulence, still or animated
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.noise = self.noise.with_octaves(octaves);
+        self
+    }
 }
 
 impl Texture for Noise {
-    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Vec3 {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
         //let p = self.scale * *p;
         //Vec3::new(1., 1., 1.) * 0.5 * (1. + self.noise.generate_noise(&p))
         //Vec3::new(1., 1., 1.) * self.noise.turbulence(&p)
-        Vec3::new(1., 1., 1.)
-            * 0.5
-            * (1. + (self.scale * p.z() + 10. * self.noise.turbulence(p)).sin())
+        let p = &point.p;
+        let turbulence = match &self.evolution {
+            Some((simplex, speed)) => {
+                simplex.turbulence4(p, speed * point.time, self.noise.octaves())
+            }
+            None => self.noise.turbulence(p),
+        };
+        Vec3::new(1., 1., 1.) * 0.5 * (1. + (self.scale * p.z() + 10. * turbulence).sin())
     }
 }
 
-pub struct ImageTexture {
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    fn to_linear(self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb => {
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::Linear => value,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    // both need ray differentials and precompute a mip pyramid, otherwise they behave as bilinear
+    Trilinear,
+    Ewa,
+}
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl Wrap {
+    fn apply(&self, i: i64, n: u32) -> u32 {
+        let n = n as i64;
+        (match self {
+            Wrap::Clamp => i.max(0).min(n - 1),
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        }) as u32
+    }
+}
+
+// applied to the uv coordinates in order: scale, rotation (in degrees) and offset
+#[derive(Copy, Clone)]
+pub struct UVTransform {
+    scale: (f64, f64),
+    offset: (f64, f64),
+    sin_theta: f64,
+    cos_theta: f64,
+}
+
+impl UVTransform {
+    pub fn new(scale: (f64, f64), offset: (f64, f64), rotation: f64) -> Self {
+        let radians = utils::degrees_to_radians(rotation);
+        UVTransform {
+            scale,
+            offset,
+            sin_theta: radians.sin(),
+            cos_theta: radians.cos(),
+        }
+    }
+    pub fn identity() -> Self {
+        UVTransform::new((1., 1.), (0., 0.), 0.)
+    }
+    fn key(&self) -> [u64; 6] {
+        [
+            self.scale.0.to_bits(),
+            self.scale.1.to_bits(),
+            self.offset.0.to_bits(),
+            self.offset.1.to_bits(),
+            self.sin_theta.to_bits(),
+            self.cos_theta.to_bits(),
+        ]
+    }
+    fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = self.apply_vector(u, v);
+        (u + self.offset.0, v + self.offset.1)
+    }
+    fn apply_vector(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        (
+            self.cos_theta * u - self.sin_theta * v,
+            self.sin_theta * u + self.cos_theta * v,
+        )
+    }
+}
+
+struct MipLevel {
     width: u32,
     height: u32,
-    bytes_per_scanline: u32,
-    bytes_per_pixel: u32,
-    image: Vec<u8>,
+    texels: Vec<Vec3>,
+}
+
+impl MipLevel {
+    fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let mut accumulator = Vec3::new(0., 0., 0.);
+                for (di, dj) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                    let (x, y) = (
+                        (2 * i + di).min(self.width - 1),
+                        (2 * j + dj).min(self.height - 1),
+                    );
+                    accumulator += self.texels[(y * self.width + x) as usize];
+                }
+                texels.push(accumulator / 4.);
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    filter: Filter,
+    wrap: (Wrap, Wrap),
+    transform: UVTransform,
 }
 
 impl ImageTexture {
-    pub fn new(filename: &str) -> Self {
-        let bytes_per_pixel = 3;
-        let img = ImageReader::open(filename)
-            .expect("Image not found.")
-            .decode()
-            .expect("Decoding failed");
+    // longest axis of the EWA ellipse relative to the shortest
+    const MAX_ANISOTROPY: f64 = 8.;
 
-        let (width, height) = (img.width(), img.height());
-        let bytes_per_scanline = bytes_per_pixel * width;
+    pub fn new(filename: &str) -> Result<Self, TextureError> {
+        ImageTexture::sampled(
+            filename,
+            ColorSpace::Srgb,
+            Filter::Nearest,
+            (Wrap::Clamp, Wrap::Clamp),
+            UVTransform::identity(),
+        )
+    }
+    // for maps that hold data rather than colors, e.g. roughness or normals
+    pub fn linear(filename: &str) -> Result<Self, TextureError> {
+        ImageTexture::sampled(
+            filename,
+            ColorSpace::Linear,
+            Filter::Nearest,
+            (Wrap::Clamp, Wrap::Clamp),
+            UVTransform::identity(),
+        )
+    }
+    pub fn sampled(
+        filename: &str,
+        color_space: ColorSpace,
+        filter: Filter,
+        wrap: (Wrap, Wrap),
+        transform: UVTransform,
+    ) -> Result<Self, TextureError> {
+        let (width, height, texels) = ImageTexture::decode(filename, color_space)?;
 
-        ImageTexture {
+        let mut levels = vec![MipLevel {
             width,
             height,
-            bytes_per_scanline,
-            bytes_per_pixel,
-            image: img.as_bytes().to_vec(),
+            texels,
+        }];
+        match filter {
+            Filter::Trilinear | Filter::Ewa => {
+                while let Some(level) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+                    let next = level.downsample();
+                    levels.push(next);
+                }
+            }
+            _ => (),
         }
+
+        Ok(ImageTexture {
+            levels,
+            filter,
+            wrap,
+            transform,
+        })
     }
-}
 
-impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3 {
-        if self.image.is_empty() {
-            Vec3::new(0., 1., 1.)
-        } else {
-            let (u, v) = (utils::clamp(u, 0., 1.), 1. - utils::clamp(v, 0., 1.));
-            let (mut i, mut j) = (
-                (u * self.width as f64) as u32,
-                (v * self.height as f64) as u32,
-            );
-            if i >= self.width {
-                i = self.width - 1;
+    pub fn memory_usage(&self) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.texels.len() * std::mem::size_of::<Vec3>())
+            .sum()
+    }
+
+    // linear texels of an image file, row by row from the top
+    pub fn decode(
+        filename: &str,
+        color_space: ColorSpace,
+    ) -> Result<(u32, u32, Vec<Vec3>), TextureError> {
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_deref() {
+            // radiance files are always linear, whatever the declared color space
+            Some("hdr") => {
+                let file = File::open(filename)?;
+                let decoder = HdrDecoder::new(BufReader::new(file))?;
+                let (width, height) = (decoder.metadata().width, decoder.metadata().height);
+                let texels = decoder
+                    .read_image_hdr()?
+                    .iter()
+                    .map(|pixel| Vec3::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+                    .collect();
+                Ok((width, height, texels))
+            }
+            // so are openexr files, half or full float channels widened to f64 and alpha dropped
+            Some("exr") => {
+                let image = read_first_rgba_layer_from_file(
+                    filename,
+                    |size, _| (size.width(), vec![Vec3::new(0., 0., 0.); size.area()]),
+                    |(width, texels), position, (r, g, b, _): (f32, f32, f32, f32)| {
+                        texels[position.y() * *width + position.x()] =
+                            Vec3::new(r as f64, g as f64, b as f64);
+                    },
+                )?;
+                let size = image.layer_data.size;
+                Ok((
+                    size.width() as u32,
+                    size.height() as u32,
+                    image.layer_data.channel_data.pixels.1,
+                ))
+            }
+            _ => {
+                // widening everything to 16 bit rgb covers 8 bit, grayscale and alpha sources alike
+                let img = ImageReader::open(filename)?.decode()?.to_rgb16();
+
+                const COLOR_SCALE: f64 = 1. / 65535.;
+                let texels = img
+                    .pixels()
+                    .map(|pixel| {
+                        Vec3::new(
+                            color_space.to_linear(COLOR_SCALE * pixel[0] as f64),
+                            color_space.to_linear(COLOR_SCALE * pixel[1] as f64),
+                            color_space.to_linear(COLOR_SCALE * pixel[2] as f64),
+                        )
+                    })
+                    .collect();
+                Ok((img.width(), img.height(), texels))
             }
-            if j >= self.height {
-                j = self.height - 1;
+        }
+    }
+
+    fn texel(&self, level: usize, i: i64, j: i64) -> Vec3 {
+        let level = &self.levels[level.min(self.levels.len() - 1)];
+        let (i, j) = (
+            self.wrap.0.apply(i, level.width),
+            self.wrap.1.apply(j, level.height),
+        );
+        level.texels[(j * level.width + i) as usize]
+    }
+
+    // continuous texel coordinates, image rows go top to bottom
+    fn texel_coordinates(&self, level: usize, u: f64, v: f64) -> (f64, f64) {
+        let level = &self.levels[level.min(self.levels.len() - 1)];
+        (u * level.width as f64, (1. - v) * level.height as f64)
+    }
+
+    fn nearest(&self, level: usize, u: f64, v: f64) -> Vec3 {
+        let (x, y) = self.texel_coordinates(level, u, v);
+        self.texel(level, x.floor() as i64, y.floor() as i64)
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Vec3 {
+        // texel centers sit at half-integer coordinates
+        let (x, y) = self.texel_coordinates(level, u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (s, t) = (x - x.floor(), y - y.floor());
+        (1. - t) * ((1. - s) * self.texel(level, i, j) + s * self.texel(level, i + 1, j))
+            + t * ((1. - s) * self.texel(level, i, j + 1) + s * self.texel(level, i + 1, j + 1))
+    }
+
+    fn bicubic(&self, level: usize, u: f64, v: f64) -> Vec3 {
+        let (x, y) = self.texel_coordinates(level, u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (weights_x, weights_y) = (
+            ImageTexture::catmull_rom(x - x.floor()),
+            ImageTexture::catmull_rom(y - y.floor()),
+        );
+        let mut accumulator = Vec3::new(0., 0., 0.);
+        for (dj, weight_y) in weights_y.iter().enumerate() {
+            for (di, weight_x) in weights_x.iter().enumerate() {
+                accumulator +=
+                    weight_x * weight_y * self.texel(level, i + di as i64 - 1, j + dj as i64 - 1);
             }
+        }
+        // catmull-rom overshoots around sharp edges
+        Vec3::new(
+            accumulator.r().max(0.),
+            accumulator.g().max(0.),
+            accumulator.b().max(0.),
+        )
+    }
 
-            const COLOR_SCALE: f64 = 1. / 255.;
-            let pixel_index = (j * self.bytes_per_scanline + i * self.bytes_per_pixel) as usize;
+    fn catmull_rom(t: f64) -> [f64; 4] {
+        let (t2, t3) = (t * t, t * t * t);
+        [
+            0.5 * (-t3 + 2. * t2 - t),
+            0.5 * (3. * t3 - 5. * t2 + 2.),
+            0.5 * (-3. * t3 + 4. * t2 + t),
+            0.5 * (t3 - t2),
+        ]
+    }
+
+    // fractional pyramid level whose texels are about width wide, in uv units
+    fn level_of_detail(&self, width: f64) -> f64 {
+        let base = &self.levels[0];
+        let texels = width * base.width.max(base.height) as f64;
+        utils::clamp(texels.max(1e-8).log2(), 0., (self.levels.len() - 1) as f64)
+    }
+
+    fn trilinear(&self, u: f64, v: f64, width: f64) -> Vec3 {
+        let lod = self.level_of_detail(width);
+        let level = lod.floor() as usize;
+        let t = lod - lod.floor();
+        if t == 0. {
+            self.bilinear(level, u, v)
+        } else {
+            (1. - t) * self.bilinear(level, u, v) + t * self.bilinear(level + 1, u, v)
+        }
+    }
+
+    fn ewa(&self, u: f64, v: f64, axes: ((f64, f64), (f64, f64))) -> Vec3 {
+        let (mut major, mut minor) = axes;
+        let length = |a: (f64, f64)| (a.0 * a.0 + a.1 * a.1).sqrt();
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
 
-            Vec3::new(
-                COLOR_SCALE * self.image[pixel_index] as f64,
-                COLOR_SCALE * self.image[pixel_index + 1] as f64,
-                COLOR_SCALE * self.image[pixel_index + 2] as f64,
+        // overly eccentric ellipses would cover too many texels, so the minor axis gets stretched
+        let (major_length, minor_length) = (length(major), length(minor));
+        if minor_length * ImageTexture::MAX_ANISOTROPY < major_length && minor_length > 0. {
+            let scale = major_length / (minor_length * ImageTexture::MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+        }
+        if length(minor) == 0. {
+            return self.bilinear(0, u, v);
+        }
+
+        let lod = self.level_of_detail(length(minor));
+        let level = lod.floor() as usize;
+        let t = lod - lod.floor();
+        (1. - t) * self.ewa_level(level, u, v, major, minor)
+            + t * self.ewa_level(level + 1, u, v, major, minor)
+    }
+
+    fn ewa_level(
+        &self,
+        level: usize,
+        u: f64,
+        v: f64,
+        major: (f64, f64),
+        minor: (f64, f64),
+    ) -> Vec3 {
+        let level = level.min(self.levels.len() - 1);
+        let (width, height) = (
+            self.levels[level].width as f64,
+            self.levels[level].height as f64,
+        );
+        let (x, y) = self.texel_coordinates(level, u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        // v runs opposite to the image rows
+        let (major, minor) = (
+            (major.0 * width, -major.1 * height),
+            (minor.0 * width, -minor.1 * height),
+        );
+
+        // implicit ellipse a*x^2 + b*x*y + c*y^2 = 1, widened by a texel so it never falls between texels
+        let mut a = major.1 * major.1 + minor.1 * minor.1 + 1.;
+        let mut b = -2. * (major.0 * major.1 + minor.0 * minor.1);
+        let mut c = major.0 * major.0 + minor.0 * minor.0 + 1.;
+        let inv_f = 1. / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det = 4. * a * c - b * b;
+        let (x_extent, y_extent) = (2. * (det * c).sqrt() / det, 2. * (a * det).sqrt() / det);
+        let (x0, x1) = ((x - x_extent).ceil() as i64, (x + x_extent).floor() as i64);
+        let (y0, y1) = ((y - y_extent).ceil() as i64, (y + y_extent).floor() as i64);
+
+        const ALPHA: f64 = 2.;
+        let (mut accumulator, mut weight_sum) = (Vec3::new(0., 0., 0.), 0.);
+        for j in y0..=y1 {
+            let dy = j as f64 - y;
+            for i in x0..=x1 {
+                let dx = i as f64 - x;
+                let r2 = a * dx * dx + b * dx * dy + c * dy * dy;
+                if r2 < 1. {
+                    let weight = (-ALPHA * r2).exp() - (-ALPHA).exp();
+                    accumulator += weight * self.texel(level, i, j);
+                    weight_sum += weight;
+                }
+            }
+        }
+
+        if weight_sum > 0. {
+            accumulator / weight_sum
+        } else {
+            self.bilinear(level, u, v)
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn sample(&self, point: &TexturePoint) -> Vec3 {
+        if self.levels[0].texels.is_empty() {
+            return Vec3::new(0., 1., 1.);
+        }
+
+        let (u, v) = self.transform.apply(point.u, point.v);
+        let axes = point.footprint.map(|f| {
+            (
+                self.transform.apply_vector(f.dudx, f.dvdx),
+                self.transform.apply_vector(f.dudy, f.dvdy),
             )
+        });
+
+        match (self.filter, axes) {
+            (Filter::Nearest, _) => self.nearest(0, u, v),
+            (Filter::Bilinear, _) => self.bilinear(0, u, v),
+            (Filter::Bicubic, _) => self.bicubic(0, u, v),
+            (Filter::Trilinear, Some((dx, dy))) => {
+                let width = 2. * dx.0.abs().max(dx.1.abs()).max(dy.0.abs()).max(dy.1.abs());
+                self.trilinear(u, v, width)
+            }
+            (Filter::Ewa, Some(axes)) => self.ewa(u, v, axes),
+            (_, None) => self.bilinear(0, u, v),
         }
     }
 }
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    Decoding(ImageError),
+    Exr(exr::error::Error),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "couldn't read texture: {}", e),
+            TextureError::Decoding(e) => write!(f, "couldn't decode texture: {}", e),
+            TextureError::Exr(e) => write!(f, "couldn't decode texture: {}", e),
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+impl From<io::Error> for TextureError {
+    fn from(e: io::Error) -> Self {
+        TextureError::Io(e)
+    }
+}
+
+impl From<ImageError> for TextureError {
+    fn from(e: ImageError) -> Self {
+        TextureError::Decoding(e)
+    }
+}
+
+impl From<exr::error::Error> for TextureError {
+    fn from(e: exr::error::Error) -> Self {
+        TextureError::Exr(e)
+    }
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct TextureKey {
+    path: PathBuf,
+    color_space: ColorSpace,
+    filter: Filter,
+    wrap: (Wrap, Wrap),
+    transform: [u64; 6],
+}
+
+// hands out the same image texture to everything that asks for the same file with the same parameters
+pub struct TextureManager {
+    images: HashMap<TextureKey, Arc<ImageTexture>>,
+}
+
+impl TextureManager {
+    pub fn new() -> Self {
+        TextureManager {
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn image(&mut self, filename: &str) -> Result<Arc<dyn Texture>, TextureError> {
+        self.image_sampled(
+            filename,
+            ColorSpace::Srgb,
+            Filter::Nearest,
+            (Wrap::Clamp, Wrap::Clamp),
+            UVTransform::identity(),
+        )
+    }
+
+    pub fn image_sampled(
+        &mut self,
+        filename: &str,
+        color_space: ColorSpace,
+        filter: Filter,
+        wrap: (Wrap, Wrap),
+        transform: UVTransform,
+    ) -> Result<Arc<dyn Texture>, TextureError> {
+        let key = TextureKey {
+            path: Path::new(filename)
+                .canonicalize()
+                .unwrap_or_else(|_| PathBuf::from(filename)),
+            color_space,
+            filter,
+            wrap,
+            transform: transform.key(),
+        };
+
+        if let Some(texture) = self.images.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Arc::new(ImageTexture::sampled(
+            filename,
+            color_space,
+            filter,
+            wrap,
+            transform,
+        )?);
+        self.images.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    // bytes taken up by the decoded texels, mip pyramids included
+    pub fn memory_usage(&self) -> usize {
+        self.images
+            .values()
+            .map(|texture| texture.memory_usage())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uv_checker_and_grid() {
+        let (black, white) = (Vec3::new_diagonal(0.), Vec3::new_diagonal(1.));
+        let p = Vec3::new(0., 0., 0.);
+
+        let checker = UVChecker::from_vec3(black, white, 4., 2.);
+        assert_eq!(checker.value(0.1, 0.1, &p).r(), 0.);
+        assert_eq!(checker.value(0.3, 0.1, &p).r(), 1.);
+        assert_eq!(checker.value(0.3, 0.6, &p).r(), 0.);
+
+        let grid = Grid::from_vec3(white, black, 10., 10., 0.1);
+        assert_eq!(grid.value(0.004, 0.55, &p).r(), 1.);
+        assert_eq!(grid.value(0.55, 0.299, &p).r(), 1.);
+        assert_eq!(grid.value(0.55, 0.55, &p).r(), 0.);
+    }
+
+    #[test]
+    fn animated_noise_evolves_over_time() {
+        let point = TexturePoint::new(0.3, 0.6, Vec3::new(0.4, 1.3, -2.1));
+        let (before, after) = (point, point.with_time(0.5));
+
+        let still = Noise::new(4.);
+        assert_eq!(still.sample(&before).r(), still.sample(&after).r());
+        let animated = Noise::animated(4., 2.);
+        assert_ne!(animated.sample(&before).r(), animated.sample(&after).r());
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let indices: Vec<u32> = (-3..7).map(|i| Wrap::Clamp.apply(i, 4)).collect();
+        assert_eq!(indices, vec![0, 0, 0, 0, 1, 2, 3, 3, 3, 3]);
+        let indices: Vec<u32> = (-3..7).map(|i| Wrap::Repeat.apply(i, 4)).collect();
+        assert_eq!(indices, vec![1, 2, 3, 0, 1, 2, 3, 0, 1, 2]);
+        let indices: Vec<u32> = (-3..7).map(|i| Wrap::Mirror.apply(i, 4)).collect();
+        assert_eq!(indices, vec![2, 1, 0, 0, 1, 2, 3, 3, 2, 1]);
+    }
+
+    #[test]
+    fn srgb_decoding() {
+        assert_eq!(ColorSpace::Srgb.to_linear(0.), 0.);
+        assert!((ColorSpace::Srgb.to_linear(1.) - 1.).abs() < 1e-12);
+        assert!((ColorSpace::Srgb.to_linear(0.5) - 0.214).abs() < 1e-3);
+        assert_eq!(ColorSpace::Linear.to_linear(0.5), 0.5);
+    }
+
+    #[test]
+    fn texture_manager_reports_missing_files() {
+        let mut textures = TextureManager::new();
+        assert!(matches!(
+            textures.image("no_such_texture.jpg"),
+            Err(TextureError::Io(_))
+        ));
+        assert_eq!(textures.len(), 0);
+    }
+
+    #[test]
+    fn decodes_openexr_into_linear_floats() {
+        // radiance above one and below the smallest 8 bit step both survive
+        let path = std::env::temp_dir().join("raytracer_decode_test.exr");
+        exr::prelude::write_rgb_file(&path, 2, 1, |x, _| {
+            if x == 0 {
+                (12.5f32, 0.25f32, 0.001f32)
+            } else {
+                (0f32, 1f32, 2f32)
+            }
+        })
+        .unwrap();
+
+        let (width, height, texels) =
+            ImageTexture::decode(path.to_str().unwrap(), ColorSpace::Srgb).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert!((texels[0] - Vec3::new(12.5, 0.25, 0.001)).length() < 1e-6);
+        assert!((texels[1] - Vec3::new(0., 1., 2.)).length() < 1e-6);
+    }
+}
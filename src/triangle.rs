@@ -1,4 +1,4 @@
-use super::{Arc, HitRecord, Hittable, Material, Ray, Vec3, AABB};
+use super::{utils, Arc, Direction, HitRecord, Hittable, Material, Position, Ray, Vec3, AABB};
 use std::f64;
 
 pub struct Triangle {
@@ -78,15 +78,19 @@ impl Hittable for Triangle {
         let outward_normal = edge1.cross(edge2).normalize();
 
         if t > f64::EPSILON && t < t_max && t > t_min {
-            Some(HitRecord::new(
-                t,
-                u,
-                v,
-                r.point(t),
-                outward_normal,
-                &r,
-                &self.material.clone(),
-            ))
+            Some(
+                HitRecord::new(
+                    t,
+                    u,
+                    v,
+                    r.point(t),
+                    outward_normal,
+                    &r,
+                    &self.material.clone(),
+                )
+                // p = v0 + u * edge1 + v * edge2
+                .with_partials(edge1, edge2),
+            )
         } else {
             None
         }
@@ -116,4 +120,29 @@ impl Hittable for Triangle {
             Vec3::new(max_x, max_y, max_z),
         ))
     }
+
+    fn area(&self) -> f64 {
+        let (edge1, edge2) = (
+            self.vertices[1] - self.vertices[0],
+            self.vertices[2] - self.vertices[0],
+        );
+        0.5 * edge1.cross(edge2).length()
+    }
+
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        // folding the unit square onto the triangle keeps the samples uniform
+        let (edge1, edge2) = (
+            self.vertices[1] - self.vertices[0],
+            self.vertices[2] - self.vertices[0],
+        );
+        let (mut u, mut v) = (utils::random_double(), utils::random_double());
+        if u + v > 1. {
+            u = 1. - u;
+            v = 1. - v;
+        }
+        Some((
+            self.vertices[0] + u * edge1 + v * edge2,
+            edge1.cross(edge2).normalize(),
+        ))
+    }
 }
@@ -30,3 +30,12 @@ pub fn random_from_range(min: f64, max: f64) -> f64 {
     let mut rng = rand::thread_rng();
     rng.gen_range(min..max)
 }
+
+// shuffled 0..n, the lattice hashing table of the noise generators
+pub fn permutation<R: Rng>(n: usize, rng: &mut R) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        p.swap(i, rng.gen_range(0..=i));
+    }
+    p
+}
@@ -155,6 +155,11 @@ impl Vec3 {
         r_out_perp + r_out_par
     }
 
+    // relative luminance of a linear sRGB color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn sqrt(&self) -> Self {
         Vec3::new(self.x().sqrt(), self.y().sqrt(), self.z().sqrt())
     }
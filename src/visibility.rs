@@ -0,0 +1,166 @@
+use super::{light::Light, ray::RayKind, Arc, Direction, HitRecord, Hittable, Position, Ray, AABB};
+
+/*
+    Hides an object from some kinds of rays, e.g. a light card that lights the subject
+    without being seen by the camera, or an object that doesn't cast shadows.
+    Light links restrict which lights illuminate the object, lights are told apart by identity,
+    so the links hold clones of the same Arcs that went into the light list or the background.
+*/
+pub struct Visibility {
+    object: Arc<dyn Hittable>,
+    camera: bool,
+    shadows: bool,
+    indirect: bool,
+    light_links: Option<Arc<Vec<Arc<dyn Light>>>>,
+}
+
+impl Visibility {
+    // visible to everything until told otherwise
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Visibility {
+            object,
+            camera: true,
+            shadows: true,
+            indirect: true,
+            light_links: None,
+        }
+    }
+    pub fn with_camera_visibility(mut self, visible: bool) -> Self {
+        self.camera = visible;
+        self
+    }
+    pub fn with_shadows(mut self, casts_shadows: bool) -> Self {
+        self.shadows = casts_shadows;
+        self
+    }
+    // seen in reflections and refractions, and by light bouncing off other surfaces
+    pub fn with_reflection_visibility(mut self, visible: bool) -> Self {
+        self.indirect = visible;
+        self
+    }
+    pub fn with_light_links(mut self, lights: Vec<Arc<dyn Light>>) -> Self {
+        self.light_links = Some(Arc::new(lights));
+        self
+    }
+}
+
+impl Hittable for Visibility {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let visible = match r.kind() {
+            RayKind::Camera => self.camera,
+            RayKind::Indirect => self.indirect,
+            RayKind::Shadow => self.shadows,
+            RayKind::Light => true,
+        };
+        if !visible {
+            return None;
+        }
+        self.object.hit(r, t_min, t_max).map(|mut hit| {
+            if self.light_links.is_some() {
+                hit.light_links = self.light_links.clone();
+            }
+            hit
+        })
+    }
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.object.bounding_box(t0, t1)
+    }
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+    fn sample_area(&self) -> Option<(Position, Direction)> {
+        self.object.sample_area()
+    }
+    fn sample_direction(&self, origin: &Position) -> Option<(Direction, f64)> {
+        self.object.sample_direction(origin)
+    }
+    fn direction_pdf(&self, origin: &Position, direction: &Direction) -> f64 {
+        self.object.direction_pdf(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        background::Constant,
+        light::{AreaLight, LightList, LightSelection, PointLight},
+        material::{DiffuseLight, Lambertian, Power},
+        texture::Solid,
+        HittableList, Sphere, Vec3, XZRect,
+    };
+
+    #[test]
+    fn hidden_from_camera_but_linked_to_one_light() {
+        let light = |x: f64| -> Arc<dyn Light> {
+            Arc::new(PointLight::new(
+                Vec3::new(x, 5., 0.),
+                Vec3::new_diagonal(1.),
+                Power::Watts(10.),
+            ))
+        };
+        let (key, fill) = (light(-1.), light(1.));
+        let card = Visibility::new(Arc::new(Sphere::new(
+            Vec3::new(0., 0., 0.),
+            1.,
+            Arc::new(Lambertian::new(Vec3::new_diagonal(0.5))),
+        )))
+        .with_camera_visibility(false)
+        .with_light_links(vec![key.clone()]);
+
+        let ray = |kind: RayKind| {
+            Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.), None).with_kind(kind)
+        };
+        assert!(card
+            .hit(&ray(RayKind::Camera), 0.001, f64::INFINITY)
+            .is_none());
+        let hit = card
+            .hit(&ray(RayKind::Indirect), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!(card
+            .hit(&ray(RayKind::Shadow), 0.001, f64::INFINITY)
+            .is_some());
+
+        assert!(hit.lit_by(key.as_ref()));
+        assert!(!hit.lit_by(fill.as_ref()));
+    }
+
+    #[test]
+    fn light_card_hidden_from_camera_still_lights_the_subject() {
+        let card: Arc<AreaLight> = Arc::new(AreaLight::new(Arc::new(
+            Visibility::new(Arc::new(Sphere::new(
+                Vec3::new(0., 2., 0.),
+                0.5,
+                Arc::new(DiffuseLight::new(Arc::new(Solid::color_vec3(
+                    Vec3::new_diagonal(4.),
+                )))),
+            )))
+            .with_camera_visibility(false),
+        )));
+        assert!(card.bounds().unwrap().phi > 0.);
+
+        let floor = Arc::new(XZRect::new(
+            -5.,
+            5.,
+            -5.,
+            5.,
+            0.,
+            Arc::new(Lambertian::new(Vec3::new_diagonal(0.5))),
+        ));
+        let world = Arc::new(HittableList::new(vec![floor, card.clone()]));
+        let lights = LightList::new(vec![card]).with_selection(LightSelection::Power);
+        let background = Constant::new(Vec3::new(0., 0., 0.));
+
+        // looking straight down through the card at the floor below it
+        let r = Ray::new(Vec3::new(0., 5., 0.), Vec3::new(0., -1., 0.), None);
+        let n = 1000;
+        let mut sum = 0.;
+        for _ in 0..n {
+            let c = crate::color(&r, &world, &lights, &background, 2, false);
+            sum += c.r();
+        }
+        let average = sum / n as f64;
+        // camera rays pass through the card, so what's left is light reflected by the floor
+        assert!(average > 0. && average < 4.);
+    }
+}
@@ -1,4 +1,7 @@
-use super::{utils, Arc, HitRecord, Hittable, Material, Ray, Texture, Vec3, AABB};
+use super::{
+    material::Dielectric, texture, utils, Arc, Color, HitRecord, Hittable, Material, Ray, Texture,
+    Vec3, AABB,
+};
 
 pub struct ConstantMedium {
     neg_inv_density: f64,
@@ -41,7 +44,8 @@ impl Hittable for ConstantMedium {
 
                 let ray_length = r.direction().length();
                 let distance_inside_boundary = (hit2.t - hit1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * utils::random_double().log(2.);
+                // exponentially distributed, so the mean free path comes out as 1 / density
+                let hit_distance = self.neg_inv_density * utils::random_double().ln();
 
                 if hit_distance > distance_inside_boundary {
                     return None;
@@ -82,8 +86,131 @@ impl Isotropic {
 impl Material for Isotropic {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
         Some((
-            self.albedo.value(rec.u, rec.v, &rec.p),
+            self.albedo.sample(&rec.texture_point()),
             Ray::new(rec.p, Vec3::random_in_unit_sphere(), Some(r_in.time())),
         ))
     }
+    // uniform phase function, there's no cosine term inside a medium
+    fn evaluate(&self, _r_in: &Ray, rec: &HitRecord, _direction: &Vec3) -> Option<Vec3> {
+        Some(self.albedo.sample(&rec.texture_point()) / (4. * std::f64::consts::PI))
+    }
+    fn samples_lights(&self) -> bool {
+        true
+    }
+}
+
+/*
+    Random walk subsurface scattering: a dielectric interface around a constant medium,
+    so light refracts in, scatters around the inside of the boundary and refracts back out.
+*/
+
+pub struct Subsurface {
+    boundary: Arc<dyn Hittable>,
+    medium: ConstantMedium,
+    interface: Arc<dyn Material>,
+}
+
+impl Subsurface {
+    // albedo here is the single scattering albedo of the medium
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        refr_index: f64,
+        mean_free_path: f64,
+        albedo: Arc<dyn Texture>,
+    ) -> Self {
+        Subsurface {
+            boundary: boundary.clone(),
+            medium: ConstantMedium::new(1. / mean_free_path, boundary, albedo),
+            interface: Arc::new(Dielectric::new(refr_index)),
+        }
+    }
+    // takes the color the surface should end up having instead of the single scattering albedo
+    pub fn from_surface_color(
+        boundary: Arc<dyn Hittable>,
+        refr_index: f64,
+        mean_free_path: f64,
+        color: Color,
+    ) -> Self {
+        let albedo = Vec3::new(
+            Subsurface::single_scattering_albedo(color.r()),
+            Subsurface::single_scattering_albedo(color.g()),
+            Subsurface::single_scattering_albedo(color.b()),
+        );
+        Subsurface::new(
+            boundary,
+            refr_index,
+            mean_free_path,
+            Arc::new(texture::Solid::color_vec3(albedo)),
+        )
+    }
+    fn single_scattering_albedo(multiple_scattering_albedo: f64) -> f64 {
+        /*
+            inversion of van de hulst's relation between the albedo of a semi-infinite slab
+            and the albedo of a single scattering event
+        */
+        let a = utils::clamp(multiple_scattering_albedo, 0., 0.999);
+        let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+        1. - s * s
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let surface_hit = self.boundary.hit(r, t_min, t_max).map(|mut hit| {
+            hit.material = self.interface.clone();
+            hit
+        });
+        let t_max = surface_hit.as_ref().map_or(t_max, |hit| hit.t);
+
+        match self.medium.hit(r, t_min, t_max) {
+            Some(medium_hit) => Some(medium_hit),
+            None => surface_hit,
+        }
+    }
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, Sphere};
+
+    // van de hulst's albedo of a semi-infinite slab for isotropic scattering
+    fn multiple_scattering_albedo(single_scattering_albedo: f64) -> f64 {
+        let s = (1. - single_scattering_albedo).sqrt();
+        (1. - s) * (1. - 0.139 * s) / (1. + 1.17 * s)
+    }
+
+    #[test]
+    fn free_flight_distances_average_the_mean_free_path() {
+        let white = Arc::new(texture::Solid::color_vec3(Vec3::new_diagonal(1.)));
+        let boundary = Arc::new(Sphere::new(
+            Vec3::new(0., 0., 0.),
+            1000.,
+            Arc::new(Lambertian::new(Vec3::new_diagonal(0.5))),
+        ));
+        let medium = ConstantMedium::new(2., boundary, white);
+        let r = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), None);
+
+        let n = 100_000;
+        let total: f64 = (0..n)
+            .map(|_| medium.hit(&r, 0., f64::INFINITY).unwrap().t)
+            .sum();
+        assert!((total / n as f64 - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn albedo_inversion_round_trips() {
+        for i in 0..=20 {
+            let color = 0.04 + 0.95 * i as f64 / 20.;
+            let single = Subsurface::single_scattering_albedo(color);
+            assert!((0. ..=1.).contains(&single));
+            assert!((multiple_scattering_albedo(single) - color).abs() < 1e-4);
+        }
+        // scattering inside the medium loses more than a single bounce off the surface
+        assert!(Subsurface::single_scattering_albedo(0.5) > 0.5);
+        assert!(Subsurface::single_scattering_albedo(0.).abs() < 1e-4);
+    }
 }
@@ -0,0 +1,61 @@
+use super::{perlin, utils, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/*
+    Cellular noise: one random feature point per unit cell,
+    the noise being the distances to the closest feature points.
+*/
+
+pub struct Worley {
+    perm: Vec<usize>,
+    offsets: Vec<Vec3>,
+}
+
+impl Worley {
+    pub fn new() -> Self {
+        Worley::seeded(perlin::DEFAULT_SEED)
+    }
+    pub fn seeded(seed: u64) -> Self {
+        let point_count: usize = 256;
+        let mut rng = StdRng::seed_from_u64(seed);
+        Worley {
+            perm: utils::permutation(point_count, &mut rng),
+            offsets: (0..point_count)
+                .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()))
+                .collect(),
+        }
+    }
+
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Vec3 {
+        let hash = self.perm[(self.perm
+            [(self.perm[(i & 255) as usize] + (j & 255) as usize) & 255]
+            + (k & 255) as usize)
+            & 255];
+        Vec3::new(i as f64, j as f64, k as f64) + self.offsets[hash]
+    }
+
+    // distances to the closest and second closest feature points
+    pub fn distances(&self, p: &Vec3) -> (f64, f64) {
+        let (i, j, k) = (
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        );
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let d = (self.feature_point(i + di, j + dj, k + dk) - *p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+}